@@ -2,24 +2,20 @@ mod shell;
 mod config;
 mod completion;
 
-use shell::{builtins, exec, state};
+use shell::{alias, builtins, editmode, exec, fc, glob, history, keybind, lexer, osstr, state};
 use shell::repl::repl_loop;
 use config::grshrc;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicBool, Ordering};
+use nu_ansi_term::Color;
 use std::io::{self, Write, IsTerminal};
 use std::os::unix::io::AsRawFd;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
 
 // Import necessari per la patch TTY e Segnali
 use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
 use nix::unistd::{self, Pid};
 
-lazy_static! {
-    static ref ALIASES: Arc<Mutex<HashMap<String, String>>> = Arc::new(Mutex::new(HashMap::new()));
-    static ref SKIP_BLOCK: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
-}
-
 fn apply_cursor_style(style: u8) {
     if style == 0 { return; }
     let mut stdout = io::stdout();
@@ -28,75 +24,66 @@ fn apply_cursor_style(style: u8) {
     let _ = stdout.flush();
 }
 
-fn get_preferred_cursor() -> u8 {
-    state::get_var("GRSH_CURSOR")
-        .and_then(|v| v.parse().ok())
-        .unwrap_or(2)
-}
-
-fn split_args(line: &str) -> Vec<String> {
-    let mut args = Vec::new();
-    let mut current = String::new();
-    let mut in_double_quotes = false;
-    let mut in_single_quotes = false;
-
-    for c in line.chars() {
-        match c {
-            '"' if !in_single_quotes => in_double_quotes = !in_double_quotes,
-            '\'' if !in_double_quotes => in_single_quotes = !in_single_quotes,
-            ' ' if !in_double_quotes && !in_single_quotes => {
-                if !current.is_empty() {
-                    args.push(current.clone());
-                    current.clear();
-                }
-            }
-            _ => current.push(c),
-        }
+fn run_line(line: String) -> bool {
+    // Buffer multilinea dal REPL (o da `-c`): eseguiamo un comando logico
+    // (o un blocco `foreach`) alla volta
+    let lines = lexer::group_blocks(lexer::numbered_lines(&line));
+    if lines.len() > 1 {
+        let mut success = true;
+        for (_, l) in lines { success = run_line(l); }
+        return success;
     }
-    if !current.is_empty() { args.push(current); }
-    args
-}
 
-fn run_line(line: String) -> bool {
     let raw_line = line.trim().to_string();
     if raw_line.is_empty() || raw_line.starts_with('#') { return true; }
 
-    {
-        let mut skip = SKIP_BLOCK.lock().unwrap();
-        if raw_line.starts_with("endif") { *skip = false; return true; }
-        if raw_line == "if" || raw_line.starts_with("if ") {
-            *skip = !raw_line.contains("$?prompt");
-            return true;
-        }
-        if *skip { return true; }
+    if raw_line.starts_with("foreach ") || raw_line.starts_with("foreach\t") {
+        let success = run_foreach(&raw_line);
+        state::set_exit_status(success);
+        return success;
     }
 
-    if ["bindkey", "set filec", "set history", "set autolist"].iter().any(|&c| raw_line.starts_with(c)) {
-        return true;
+    if lexer::if_header(&raw_line).is_some() {
+        let success = run_if(&raw_line);
+        state::set_exit_status(success);
+        return success;
+    }
+    let first_word = raw_line.split_whitespace().next().unwrap_or("");
+    if first_word == "else" || first_word == "endif" {
+        eprintln!("grsh: {}: senza if", first_word);
+        state::set_exit_status(false);
+        return false;
     }
 
-    let mut expanded_line = state::expand_env_vars(&raw_line);
+    let expanded_line = state::expand_env_vars(&raw_line);
 
-    // --- PATCH ALIAS: Risoluzione immediata ---
-    let first_word = expanded_line.split_whitespace().next().unwrap_or("").to_string();
-    {
-        let aliases = ALIASES.lock().unwrap();
-        if let Some(alias_value) = aliases.get(&first_word) {
-            expanded_line = expanded_line.replacen(&first_word, alias_value, 1);
-        }
+    // Gestione Pipes e Redirezioni (solo operatori fuori da virgolette ed escape)
+    if ["|", ">", "<", " && "].iter().any(|op| lexer::find_unquoted(&expanded_line, op).is_some()) {
+        let success = exec::execute(&expanded_line.replace("|&", "|"));
+        state::set_exit_status(success);
+        return success;
     }
-    // ------------------------------------------
 
-    // Gestione Pipes e Redirezioni
-    if expanded_line.contains('|') || expanded_line.contains('>') ||
-       expanded_line.contains('<') || expanded_line.contains(" && ") {
-        let success = exec::execute(&expanded_line.replace("|&", "|"));
+    // Gli alias si espandono qui solo per riconoscere i builtin (`alias h history 25`);
+    // per i comandi esterni ci pensa exec, comando per comando
+    let words = lexer::smart_split(&alias::expand_aliases(&expanded_line));
+    let Some(first) = words.first() else { return true; };
+    let first = glob::unescape(first);
+    if !builtins::is_builtin(&first) {
+        let success = exec::execute(&expanded_line);
         state::set_exit_status(success);
         return success;
     }
 
-    let parts = split_args(&expanded_line);
-    if parts.is_empty() { return true; }
+    let parts = match exec::expand_words(words.iter().map(|s| s.as_str()).collect()) {
+        Ok(parts) if !parts.is_empty() => parts,
+        Ok(_) => return true,
+        Err(e) => {
+            eprintln!("grsh: {}", e);
+            state::set_exit_status(false);
+            return false;
+        }
+    };
 
     let cmd = parts[0].as_str();
     let args: Vec<&str> = parts.iter().skip(1).map(|s| s.as_str()).collect();
@@ -110,11 +97,14 @@ fn run_line(line: String) -> bool {
             let mut is_n = false;
             let msg: Vec<String> = args.iter()
                 .filter(|&&a| if a == "-n" { is_n = true; false } else { true })
-                .map(|&a| a.trim_matches('"').trim_matches('\'').to_string())
+                .map(|&a| a.to_string())
                 .collect();
-            let output = msg.join(" ");
-            if is_n { print!("{}", output); let _ = io::stdout().flush(); }
-            else { println!("{}", output); }
+            // Scriviamo i byte esatti: gli argomenti possono contenere nomi non UTF-8
+            let mut output = osstr::decode(&msg.join(" ")).into_vec();
+            if !is_n { output.push(b'\n'); }
+            let mut stdout = io::stdout();
+            let _ = stdout.write_all(&output);
+            let _ = stdout.flush();
             true
         },
         "set" | "setenv" => {
@@ -123,21 +113,45 @@ fn run_line(line: String) -> bool {
                 let val = if args.len() > 2 && args[1] == "=" { args[2] } else { args[1] };
                 let final_val = val.trim_matches('"').trim_matches('\'').trim_matches(')');
                 state::set_var(key, final_val);
-                if key == "GRSH_CURSOR" {
-                    if let Ok(num) = final_val.parse::<u8>() { apply_cursor_style(num); }
+                if key == "GRSH_CURSOR" || key == "editmode" {
+                    apply_cursor_style(editmode::prompt_cursor());
                 }
+            } else if let Some(flag) = args.first() {
+                // Opzione booleana in stile tcsh: `set nullglob`, resta nella shell
+                state::set_shell_var(flag, "");
             }
             true
         },
         "alias" => {
-            if let Some((name, val)) = expanded_line.replacen("alias ", "", 1).split_once('=') {
-                ALIASES.lock().unwrap().insert(name.trim().to_string(), val.trim().to_string());
-            } else if args.len() >= 2 {
-                ALIASES.lock().unwrap().insert(args[0].to_string(), args[1..].join(" "));
+            if args.is_empty() {
+                builtins::handle_builtin("alias", &[])
+            } else {
+                if let Some((name, val)) = expanded_line.replacen("alias ", "", 1).split_once('=') {
+                    alias::add_alias(name.trim(), val.trim());
+                } else if args.len() >= 2 {
+                    alias::add_alias(args[0], &args[1..].join(" "));
+                }
+                true
             }
-            true
         },
-        "exit" | "quit" => std::process::exit(0),
+        "fc" => match fc::fc(&args) {
+            Ok(Some(commands)) => {
+                // Come in bash mostriamo i comandi prima di eseguirli
+                println!("{}", commands);
+                history::replace_fc(&commands);
+                run_line(osstr::encode_str(&commands))
+            }
+            Ok(None) => true,
+            Err(msg) => { eprintln!("{}", msg); false }
+        },
+        "history" => builtins::history_builtin(&args),
+        "bindkey" => {
+            let ok = keybind::bindkey(&args);
+            apply_cursor_style(editmode::prompt_cursor());
+            ok
+        },
+        "reload" => reload(),
+        "exit" | "quit" => { fc::cleanup(); std::process::exit(0) },
         _ => {
             if builtins::handle_builtin(cmd, &args) {
                 true
@@ -151,16 +165,193 @@ fn run_line(line: String) -> bool {
     success
 }
 
+// `foreach var (parole)` ... `end`: il corpo viene eseguito una volta per ogni
+// parola (dopo variabili e glob), con `$var` come variabile di shell
+fn run_foreach(block: &str) -> bool {
+    let mut lines: Vec<&str> = block.lines().collect();
+    let header = lines.remove(0).trim();
+    if lines.last().map(|l| l.trim()) != Some("end") {
+        eprintln!("grsh: foreach: manca end");
+        return false;
+    }
+    lines.pop();
+
+    let parsed = header.strip_prefix("foreach")
+        .and_then(|rest| rest.trim().split_once(char::is_whitespace))
+        .and_then(|(name, list)| Some((name, list.trim().strip_prefix('(')?.strip_suffix(')')?)))
+        .filter(|(name, _)| !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_'));
+    let Some((name, list)) = parsed else {
+        eprintln!("grsh: foreach: sintassi: foreach variabile (parole)");
+        return false;
+    };
+
+    let words = lexer::smart_split(&state::expand_env_vars(list));
+    let values = match exec::expand_words(words.iter().map(|s| s.as_str()).collect()) {
+        Ok(values) => values,
+        Err(e) => { eprintln!("grsh: {}", e); return false; }
+    };
+    let body = lines.join("\n");
+    let mut success = true;
+    for value in values {
+        state::set_shell_var(name, &value);
+        success = run_line(body.clone());
+    }
+    success
+}
+
+// `if (cond) comando` su una riga, oppure il blocco `if (cond) then` ...
+// [`else if (cond) then` ...] [`else` ...] `endif`: si esegue il primo ramo con la
+// condizione vera. Una condizione falsa non è un errore.
+fn run_if(block: &str) -> bool {
+    let mut lines: Vec<&str> = block.lines().collect();
+    let header = lines.remove(0).trim();
+    let Some((cond, rest)) = lexer::if_header(header).and_then(split_condition) else {
+        eprintln!("grsh: if: sintassi: if (condizione) then");
+        return false;
+    };
+
+    if rest != "then" {
+        return match shell::cond::eval(cond) {
+            Ok(true) => run_line(rest.to_string()),
+            Ok(false) => true,
+            Err(e) => { eprintln!("grsh: {}", e); false }
+        };
+    }
+    if lines.last().map(|l| l.trim()) != Some("endif") {
+        eprintln!("grsh: if: manca endif");
+        return false;
+    }
+    lines.pop();
+
+    // Rami al primo livello: (condizione, corpo); `else` da solo non ha condizione
+    let mut branches: Vec<(Option<&str>, Vec<&str>)> = vec![(Some(cond), Vec::new())];
+    let mut depth = 0usize;
+    for line in lines {
+        let trimmed = line.trim();
+        if depth == 0 {
+            if trimmed == "else" {
+                branches.push((None, Vec::new()));
+                continue;
+            }
+            if let Some(other) = trimmed.strip_prefix("else").and_then(lexer::if_header) {
+                match split_condition(other) {
+                    Some((cond, "then")) => branches.push((Some(cond), Vec::new())),
+                    _ => {
+                        eprintln!("grsh: if: sintassi: else if (condizione) then");
+                        return false;
+                    }
+                }
+                continue;
+            }
+        }
+        match lexer::block_step(line) {
+            Some(true) => depth += 1,
+            Some(false) => depth = depth.saturating_sub(1),
+            None => {}
+        }
+        if let Some((_, body)) = branches.last_mut() { body.push(line); }
+    }
+
+    for (cond, body) in branches {
+        let taken = match cond.map(shell::cond::eval) {
+            None | Some(Ok(true)) => true,
+            Some(Ok(false)) => false,
+            Some(Err(e)) => { eprintln!("grsh: {}", e); return false; }
+        };
+        if taken {
+            return body.is_empty() || run_line(body.join("\n"));
+        }
+    }
+    true
+}
+
+// `(condizione) resto`: il testo tra le parentesi esterne e quello che segue
+fn split_condition(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start().strip_prefix('(')?;
+    let mut depth = 1usize;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 { return Some((&text[..i], text[i + 1..].trim())); }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
 fn run_file(path: &str) {
     let full_path = if path.starts_with('~') {
         path.replacen('~', &std::env::var("HOME").unwrap_or_default(), 1)
     } else { path.to_string() };
 
-    if let Ok(content) = std::fs::read_to_string(&full_path) {
-        for line in content.lines() { run_line(line.to_string()); }
+    if let Ok(content) = osstr::read_file(Path::new(&osstr::decode(&full_path))) {
+        for (_, line) in lexer::group_blocks(lexer::numbered_lines(&content)) { run_line(line); }
     } else {
-        eprintln!("grsh: errore nel leggere il file: {}", path);
+        eprintln!("grsh: errore nel leggere il file: {}", osstr::display(path));
+    }
+}
+
+// Script eseguiti all'avvio, in ordine: conf.d di sistema, conf.d dell'utente, ~/.grshrc
+fn startup_scripts() -> Vec<PathBuf> {
+    let mut scripts = shell::config::snippets();
+    scripts.push(grshrc::path());
+    scripts
+}
+
+// Vero mentre si eseguono i file di avvio: un `reload` lì dentro (o `set autoreload`)
+// li rieseguirebbe all'infinito
+static LOADING: AtomicBool = AtomicBool::new(false);
+
+// config.toml, poi gli script riga per riga; gli errori indicano file e riga
+fn load_startup_files() {
+    LOADING.store(true, Ordering::Relaxed);
+    for error in shell::config::load() { eprintln!("{}", error); }
+    let scripts = startup_scripts();
+    let mut loaded: Vec<PathBuf> = Some(shell::config::file()).filter(|f| f.is_file()).into_iter().collect();
+    for script in &scripts {
+        if !script.is_file() { continue; }
+        loaded.push(script.clone());
+        for (number, line) in grshrc::load(script) {
+            if !run_line(line.clone()) {
+                eprintln!("grsh: {}:{}: errore (stato {}): {}", script.display(), number, state::last_status(), line);
+            }
+        }
+    }
+    // Anche le directory conf.d: cambiano quando si aggiunge o toglie uno script
+    let mut watched = vec![shell::config::file()];
+    watched.extend(shell::config::conf_dirs());
+    watched.extend(scripts);
+    shell::config::watch(watched);
+    shell::config::set_loaded(loaded);
+    LOADING.store(false, Ordering::Relaxed);
+}
+
+// `grsh --list-config`: i file che verrebbero letti all'avvio, nell'ordine
+// (nella sessione, il builtin `list-config` mostra quelli letti davvero)
+fn list_config() {
+    let config = shell::config::file();
+    let files: Vec<PathBuf> = std::iter::once(config).chain(startup_scripts()).filter(|f| f.is_file()).collect();
+    builtins::print_config_files("File di configurazione che verrebbero letti, in ordine:", &files);
+}
+
+// Riparte dai valori predefiniti e rilegge i file di avvio; il REPL ricostruisce
+// l'editor al prompt successivo
+fn reload() -> bool {
+    if LOADING.load(Ordering::Relaxed) {
+        eprintln!("grsh: reload: non si può usare nei file di avvio");
+        return false;
     }
+    state::reset_vars();
+    alias::clear();
+    keybind::reset();
+    load_startup_files();
+    history::reopen();
+    apply_cursor_style(editmode::prompt_cursor());
+    println!("{}", Color::Purple.bold().paint("Configurazione ricaricata"));
+    true
 }
 
 fn main() {
@@ -180,29 +371,33 @@ fn main() {
         }
     }
 
-    let args: Vec<String> = std::env::args().collect();
+    state::remember_environment();
+    // Gli argomenti possono non essere UTF-8: restano byte esatti fino a exec
+    let args: Vec<String> = std::env::args_os().map(|a| osstr::encode(&a)).collect();
 
     if args.len() > 1 {
         match args[1].as_str() {
             "-c" => { if args.len() > 2 { run_line(args[2].clone()); } return; },
             "--version" | "-v" | "version" => { builtins::handle_builtin("version", &[]); return; },
             "--help" | "-h" | "help" => { builtins::handle_builtin("help", &[]); return; },
+            "--list-config" => { list_config(); return; },
             arg if arg.starts_with('-') => {
-                eprintln!("grsh: flag sconosciuto: {}", arg);
+                eprintln!("grsh: flag sconosciuto: {}", osstr::display(arg));
                 std::process::exit(1);
             },
             filename => { run_file(filename); return; }
         }
     }
 
-    for line in grshrc::load() { run_line(line); }
+    load_startup_files();
 
     let term = std::env::var("TERM").unwrap_or_default();
-    apply_cursor_style(get_preferred_cursor());
+    apply_cursor_style(editmode::prompt_cursor());
 
     if !is_atty || term == "" || term == "dumb" {
         return;
     } else {
         repl_loop(run_line);
+        fc::cleanup();
     }
 }
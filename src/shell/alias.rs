@@ -11,9 +11,31 @@ pub fn add_alias(name: &str, value: &str) {
     map.insert(name.to_string(), value.to_string());
 }
 
-pub fn resolve_alias(name: &str) -> String {
-    let map = ALIAS_MAP.lock().unwrap();
-    map.get(name).cloned().unwrap_or_else(|| name.to_string())
+/// Rimuove tutti gli alias (per `reload`)
+pub fn clear() {
+    ALIAS_MAP.lock().unwrap().clear();
+}
+
+pub fn get_alias(name: &str) -> Option<String> {
+    ALIAS_MAP.lock().unwrap().get(name).cloned()
+}
+
+/// Sostituisce l'alias nella prima parola del comando, anche a catena
+/// (`ll` -> `ls -lAFG` -> `ls -G -lAFG`), senza mai riusare lo stesso alias.
+/// Una parola quotata o con `\` (es. `\ls`) non viene espansa.
+pub fn expand_aliases(line: &str) -> String {
+    let mut current = line.trim_start().to_string();
+    let mut used: Vec<String> = Vec::new();
+    loop {
+        let first = current.split_whitespace().next().unwrap_or("").to_string();
+        if first.is_empty() || first.contains(['\\', '\'', '"']) || used.contains(&first) {
+            return current;
+        }
+        let Some(value) = get_alias(&first) else { return current; };
+        let value = value.trim_matches(|c| c == '\'' || c == '"');
+        current = format!("{}{}", value, &current[first.len()..]);
+        used.push(first);
+    }
 }
 
 // Questa è la funzione che mancava!
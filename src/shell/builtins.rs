@@ -2,7 +2,8 @@ use std::env;
 use std::time::Instant;
 use std::os::unix::process::CommandExt;
 use nu_ansi_term::Color;
-use crate::shell::{alias, state};
+use crate::shell::{alias, config, fc, history, osstr, resolver, state};
+use crate::shell::resolver::Resolution;
 
 // Import necessari per la gestione processi in fg
 use nix::unistd::{self, Pid};
@@ -101,6 +102,7 @@ pub fn handle_builtin(cmd: &str, args: &[&str]) -> bool {
             if !jobs.is_empty() {
                 println!("{}", Color::Yellow.bold().paint("Attenzione: ci sono job sospesi. Usa 'zap' o digita di nuovo exit."));
             }
+            fc::cleanup();
             std::process::exit(0)
         }
 
@@ -110,6 +112,11 @@ pub fn handle_builtin(cmd: &str, args: &[&str]) -> bool {
             true
         }
 
+        "list-config" => {
+            print_config_files("File di configurazione letti, in ordine:", &config::loaded());
+            true
+        }
+
         // --- NAVIGAZIONE ---
         "cd" => {
             let home = env::var("HOME").unwrap_or_else(|_| "/".into());
@@ -121,8 +128,8 @@ pub fn handle_builtin(cmd: &str, args: &[&str]) -> bool {
                     args.join(" ") 
             };
 
-            if let Err(e) = env::set_current_dir(&full_path) {
-                    eprintln!("grsh: cd: {}: {}", full_path, e);
+            if let Err(e) = env::set_current_dir(osstr::decode(&full_path)) {
+                    eprintln!("grsh: cd: {}: {}", osstr::display(&full_path), e);
             }
             true
         }
@@ -135,10 +142,10 @@ pub fn handle_builtin(cmd: &str, args: &[&str]) -> bool {
 
         "mkcd" => {
             if let Some(dir) = args.first() {
-                if let Err(e) = std::fs::create_dir_all(dir) {
-                    eprintln!("grsh: mkcd: {}: {}", dir, e);
+                if let Err(e) = std::fs::create_dir_all(osstr::decode(dir)) {
+                    eprintln!("grsh: mkcd: {}: {}", osstr::display(dir), e);
                 } else { 
-                    let _ = env::set_current_dir(dir); 
+                    let _ = env::set_current_dir(osstr::decode(dir)); 
                 }
             } else {
                 eprintln!("grsh: mkcd: specificare una directory");
@@ -147,14 +154,6 @@ pub fn handle_builtin(cmd: &str, args: &[&str]) -> bool {
         }
 
         // --- AMBIENTE E CONFIGURAZIONE ---
-        "reload" => {
-            println!("{}", Color::Purple.bold().paint("Ricaricamento configurazione ~/.grshrc..."));
-            let home = env::var("HOME").unwrap_or_else(|_| "/root".into());
-            let rc_path = format!("{}/.grshrc", home);
-            println!("Nota: Usa 'source {}' per applicare le modifiche.", rc_path);
-            true
-        }
-
         "setenv" => {
             if args.is_empty() {
                 for (k, v) in env::vars() { println!("{}={}", k, v); }
@@ -166,6 +165,11 @@ pub fn handle_builtin(cmd: &str, args: &[&str]) -> bool {
             true
         }
 
+        "unset" => {
+            for name in args { state::unset_var(name); }
+            true
+        }
+
         "unsetenv" => {
             if let Some(key) = args.first() {
                 env::remove_var(key);
@@ -193,6 +197,11 @@ pub fn handle_builtin(cmd: &str, args: &[&str]) -> bool {
             true
         }
 
+        "history" => {
+            history_builtin(args);
+            true
+        }
+
         // --- UTILITY ---
         "calc" => {
             if args.is_empty() {
@@ -224,20 +233,26 @@ pub fn handle_builtin(cmd: &str, args: &[&str]) -> bool {
         }
 
         "type" => {
-            if let Some(name) = args.first() {
-                if alias::get_all_aliases().iter().any(|(n, _)| n == *name) { 
-                    println!("{} è un alias", name); 
+            for name in args {
+                match resolver::resolve(name) {
+                    Resolution::Alias(value) => println!("{} è un alias di '{}'", name, value),
+                    Resolution::Builtin => println!("{} è un built-in di grsh", name),
+                    Resolution::Path(path) | Resolution::File(path) => println!("{} è {}", name, path.display()),
+                    Resolution::Unknown => eprintln!("grsh: type: {} non trovato", name),
                 }
-                else if is_builtin(name) { println!("{} è un built-in di grsh", name); }
-                else if let Some(path) = state::find_in_path(name) { println!("{} è {}", name, path.display()); }
             }
             true
         }
 
+        "rehash" => {
+            resolver::rehash();
+            true
+        }
+
         "exec" => {
             if let Some(bin) = args.first() {
-                let mut c = std::process::Command::new(bin);
-                if args.len() > 1 { c.args(&args[1..]); }
+                let mut c = std::process::Command::new(osstr::decode(bin));
+                c.args(args[1..].iter().map(|a| osstr::decode(a)));
                 let _ = c.exec();
                 eprintln!("grsh: exec fallito");
             }
@@ -248,13 +263,99 @@ pub fn handle_builtin(cmd: &str, args: &[&str]) -> bool {
     }
 }
 
-fn is_builtin(name: &str) -> bool {
-    let b = [
-        "exit", "quit", "cd", "pwd", "mkcd", "calc", "sysinfo", 
-        "which", "type", "setenv", "unsetenv", "env", "exec", 
-        "version", "alias", "help", "reload", "jobs", "fg", "zap"
-    ];
-    b.contains(&name)
+/// Elenco numerato di file di configurazione, seguito dalle directory `conf.d` cercate
+pub fn print_config_files(title: &str, files: &[std::path::PathBuf]) {
+    println!("{}", Color::Purple.bold().paint(title));
+    for (index, file) in files.iter().enumerate() {
+        println!("  {}. {}", index + 1, file.display());
+    }
+    if files.is_empty() { println!("  nessuno"); }
+    let dirs: Vec<String> = config::conf_dirs().iter().map(|d| d.display().to_string()).collect();
+    println!("Directory conf.d (*.grsh): {}", dirs.join(", "));
+}
+
+// Comandi gestiti direttamente da run_line in main.rs (oltre a quelli qui sopra)
+pub const LINE_BUILTINS: &[&str] = &[
+    "source", "echo", "set", "setenv", "alias", "exit", "quit", "if", "endif", "foreach", "end", "bindkey", "fc",
+    "reload",
+];
+
+const BUILTINS: &[&str] = &[
+    "exit", "quit", "cd", "pwd", "mkcd", "calc", "sysinfo",
+    "which", "type", "setenv", "unsetenv", "env", "exec",
+    "version", "alias", "help", "jobs", "fg", "zap", "unset",
+    "history", "rehash", "list-config",
+];
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.contains(&name) || LINE_BUILTINS.contains(&name)
+}
+
+/// Tutti i nomi dei builtin
+pub fn names() -> impl Iterator<Item = &'static str> {
+    BUILTINS.iter().chain(LINE_BUILTINS).copied()
+}
+
+// history [n] [--grep pat] [--cwd] [--since 1h] | -c | -d n | -w | -r
+/// `history`: falso su opzioni sbagliate e errori della cronologia, così `$status` lo vede
+pub fn history_builtin(args: &[&str]) -> bool {
+    let mut limit = None;
+    let mut grep = None;
+    let mut cwd = None;
+    let mut since = None;
+
+    let mut iter = args.iter();
+    while let Some(&arg) = iter.next() {
+        match arg {
+            "-c" => {
+                if let Err(e) = history::clear() { eprintln!("grsh: history: {}", e); return false; }
+                return true;
+            }
+            "-d" => {
+                return match iter.next().and_then(|n| n.parse().ok()) {
+                    Some(n) => match history::delete(n) {
+                        Ok(()) => true,
+                        Err(e) => { eprintln!("grsh: history: {}", e); false }
+                    },
+                    None => { eprintln!("grsh: history: -d richiede il numero della voce"); false }
+                };
+            }
+            "-w" => {
+                if let Err(e) = history::write() { eprintln!("grsh: history: {}", e); return false; }
+                return true;
+            }
+            "-r" => { history::pull(); return true; }
+            "--grep" => match iter.next() {
+                Some(pat) => grep = Some(pat.to_string()),
+                None => { eprintln!("grsh: history: --grep richiede un testo"); return false; }
+            },
+            "--cwd" => cwd = env::current_dir().ok().map(|d| osstr::encode(d.as_os_str())),
+            "--since" => match iter.next().and_then(|s| history::parse_duration(s)) {
+                Some(secs) => since = Some(secs),
+                None => { eprintln!("grsh: history: --since richiede una durata (es: 30m, 1h, 2d)"); return false; }
+            },
+            n if n.parse::<usize>().is_ok() => limit = n.parse().ok(),
+            other => { eprintln!("grsh: history: opzione sconosciuta: {}", other); return false; }
+        }
+    }
+
+    let search = history::Search {
+        text: grep.map(history::Text::Contains),
+        cwd,
+        since: since.map(|secs| chrono::Utc::now() - chrono::Duration::seconds(secs)),
+    };
+    if (search.cwd.is_some() || search.since.is_some()) && !history::has_metadata() {
+        eprintln!("grsh: history: questa cronologia non registra directory e orari dei comandi");
+        return false;
+    }
+
+    for (n, item) in history::latest(search, limit) {
+        match item.start_timestamp {
+            Some(t) => println!("{:>6}  {}  {}", n, t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"), item.command_line),
+            None => println!("{:>6}  {}", n, item.command_line),
+        }
+    }
+    true
 }
 
 fn print_help() {
@@ -275,16 +376,24 @@ fn print_help() {
     println!("\n{}", Color::Cyan.bold().paint("--- Ambiente & Configurazione ---"));
     println!("  setenv K V       Imposta una variabile d'ambiente");
     println!("  unsetenv K       Rimuove una variabile d'ambiente");
+    println!("  unset N          Rimuove una variabile o un'opzione (es: nullglob)");
     println!("  env              Mostra tutte le variabili d'ambiente");
     println!("  alias N='C'      Crea un alias per un comando");
     println!("  source <file>    Esegue i comandi da un file");
-    println!("  reload           Info su ricaricamento ~/.grshrc");
+    println!("  reload           Rilegge config.toml, conf.d e ~/.grshrc (`set autoreload`: automatico)");
+    println!("  list-config      File di configurazione letti in questa sessione, in ordine");
+    println!("  grsh --list-config  File che verrebbero letti all'avvio, senza eseguirli");
     println!("  exec <cmd>       Sostituisce la shell con un altro processo");
+    println!("  history [n]      Cronologia (--grep T, --cwd, --since 1h, -d n, -c, -w, -r)");
+    println!("  fc [-l|-s] [n]   Modifica in $EDITOR ed esegue comandi della cronologia");
+    println!("  bindkey -v | -e  Editing in stile vi o emacs (anche: set editmode = vi)");
+    println!("  bindkey K azione Lega un tasto (es: ^X^R clear-screen; -c cmd, -r, -l, -a)");
 
     println!("\n{}", Color::Cyan.bold().paint("--- Utility & Sistema ---"));
     println!("  calc <expr>      Calcolatrice (es: calc \"(5+3)*2\")");
     println!("  which <cmd>      Trova il percorso di un eseguibile");
     println!("  type <cmd>       Descrive la natura del comando");
+    println!("  rehash           Rilegge gli eseguibili del PATH");
     println!("  sysinfo          Info sistema e uptime shell");
 
     println!("\n{}", Color::Cyan.bold().paint("--- Sessione ---"));
@@ -0,0 +1,255 @@
+use std::env;
+use std::fs;
+use std::io::IsTerminal;
+use nix::unistd::{access, AccessFlags};
+use crate::shell::{glob, osstr, state};
+
+// --- CONDIZIONI DI `if` IN STILE TCSH ---
+// Il sottoinsieme delle espressioni di csh che serve negli script: `$?var`, test sui
+// file (`-e -f -d -s -z -r -w -x`), confronti tra parole (`==`, `!=`, `=~`, `!~` con
+// pattern di glob) e tra numeri (`<`, `>`, `<=`, `>=`), `!`, `&&`, `||` e parentesi.
+
+#[derive(Debug, PartialEq)]
+enum Tok {
+    Word(String),
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+const OPERATORS: &[&str] = &["&&", "||", "==", "!=", "=~", "!~", "<=", ">=", "!", "<", ">"];
+
+/// Valuta la condizione di `if (...)`: il testo tra le parentesi, con le variabili
+/// ancora da sostituire
+pub fn eval(expr: &str) -> Result<bool, String> {
+    let expanded = state::expand_env_vars(&defined_vars(expr));
+    let tokens = tokenize(&expanded);
+    let mut parser = Parser { tokens: &tokens, pos: 0 };
+    let value = parser.or()?;
+    if parser.pos < tokens.len() {
+        return Err(format!("if: espressione non valida: {}", osstr::display(expanded.trim())));
+    }
+    Ok(truthy(&value))
+}
+
+// `$?nome` diventa 1 se la variabile esiste, 0 altrimenti. Come in tcsh `prompt`
+// esiste nelle shell interattive anche se non è stato impostato.
+fn defined_vars(expr: &str) -> String {
+    let mut out = String::new();
+    let mut rest = expr;
+    while let Some(i) = rest.find("$?") {
+        out.push_str(&rest[..i]);
+        let after = &rest[i + 2..];
+        let len = after.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(after.len());
+        if len == 0 {
+            // `$?` da solo è il codice d'uscita: ci pensa `expand_env_vars`
+            out.push_str("$?");
+        } else {
+            let name = &after[..len];
+            let defined = state::get_var(name).is_some()
+                || env::var_os(name).is_some()
+                || (name == "prompt" && std::io::stdin().is_terminal());
+            out.push(if defined { '1' } else { '0' });
+        }
+        rest = &after[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn tokenize(expr: &str) -> Vec<Tok> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.char_indices().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => { chars.next(); }
+            '(' => { chars.next(); tokens.push(Tok::Open); }
+            ')' => { chars.next(); tokens.push(Tok::Close); }
+            '"' | '\'' => {
+                chars.next();
+                let word: String = chars.by_ref().map(|(_, n)| n).take_while(|&n| n != c).collect();
+                tokens.push(Tok::Word(word));
+            }
+            _ => {
+                if let Some(op) = OPERATORS.iter().find(|op| expr[i..].starts_with(**op)) {
+                    for _ in 0..op.len() { chars.next(); }
+                    tokens.push(Tok::Op(op));
+                    continue;
+                }
+                let mut word = String::new();
+                while let Some(&(_, n)) = chars.peek() {
+                    if n.is_whitespace() || "()&|=!<>\"'".contains(n) { break; }
+                    word.push(n);
+                    chars.next();
+                }
+                tokens.push(Tok::Word(word));
+            }
+        }
+    }
+    tokens
+}
+
+// Valori come in csh: parole, con i booleani scritti `1` e `0`
+fn truthy(value: &str) -> bool {
+    !value.is_empty() && value != "0"
+}
+
+fn boolean(b: bool) -> String {
+    if b { "1".to_string() } else { "0".to_string() }
+}
+
+fn number(word: &str) -> Result<i64, String> {
+    word.parse().map_err(|_| format!("if: numero non valido: {}", osstr::display(word)))
+}
+
+// Test sui file: `-e percorso`. I permessi sono quelli dell'utente corrente secondo
+// access(2), come in tcsh: i bit del modo da soli non dicono chi può usare il file.
+fn file_test(op: char, word: &str) -> bool {
+    let path = osstr::decode(word);
+    let meta = fs::metadata(&path);
+    let mode = |flags: AccessFlags| access(path.as_os_str(), flags).is_ok();
+    match op {
+        'e' => meta.is_ok(),
+        'f' => meta.as_ref().is_ok_and(|m| m.is_file()),
+        'd' => meta.as_ref().is_ok_and(|m| m.is_dir()),
+        's' => meta.as_ref().is_ok_and(|m| m.len() > 0),
+        'z' => meta.as_ref().is_ok_and(|m| m.len() == 0),
+        'r' => mode(AccessFlags::R_OK),
+        'w' => mode(AccessFlags::W_OK),
+        'x' => mode(AccessFlags::X_OK),
+        _ => false,
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Tok],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek_op(&self, op: &str) -> bool {
+        matches!(self.tokens.get(self.pos), Some(Tok::Op(o)) if *o == op)
+    }
+
+    fn or(&mut self) -> Result<String, String> {
+        let mut value = self.and()?;
+        while self.peek_op("||") {
+            self.pos += 1;
+            let right = self.and()?;
+            value = boolean(truthy(&value) || truthy(&right));
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<String, String> {
+        let mut value = self.not()?;
+        while self.peek_op("&&") {
+            self.pos += 1;
+            let right = self.not()?;
+            value = boolean(truthy(&value) && truthy(&right));
+        }
+        Ok(value)
+    }
+
+    fn not(&mut self) -> Result<String, String> {
+        if self.peek_op("!") {
+            self.pos += 1;
+            return Ok(boolean(!truthy(&self.not()?)));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<String, String> {
+        let left = self.primary()?;
+        let Some(Tok::Op(op)) = self.tokens.get(self.pos) else { return Ok(left); };
+        if ["&&", "||", "!"].contains(op) { return Ok(left); }
+        self.pos += 1;
+        let right = self.primary()?;
+        let result = match *op {
+            "==" => left == right,
+            "!=" => left != right,
+            "=~" => glob::Pattern::new(&right, false).matches(&left),
+            "!~" => !glob::Pattern::new(&right, false).matches(&left),
+            "<" => number(&left)? < number(&right)?,
+            ">" => number(&left)? > number(&right)?,
+            "<=" => number(&left)? <= number(&right)?,
+            _ => number(&left)? >= number(&right)?,
+        };
+        Ok(boolean(result))
+    }
+
+    fn primary(&mut self) -> Result<String, String> {
+        let token = self.tokens.get(self.pos).ok_or("if: espressione incompleta")?;
+        self.pos += 1;
+        match token {
+            Tok::Open => {
+                let value = self.or()?;
+                match self.tokens.get(self.pos) {
+                    Some(Tok::Close) => { self.pos += 1; Ok(value) }
+                    _ => Err("if: manca `)`".to_string()),
+                }
+            }
+            Tok::Word(word) => {
+                let mut flag = word.chars();
+                if let (Some('-'), Some(op), None) = (flag.next(), flag.next(), flag.next()) {
+                    if "efdszrwx".contains(op) {
+                        if let Some(Tok::Word(path)) = self.tokens.get(self.pos) {
+                            self.pos += 1;
+                            return Ok(boolean(file_test(op, path)));
+                        }
+                    }
+                }
+                Ok(word.clone())
+            }
+            Tok::Op(op) => Err(format!("if: operatore inatteso: {}", op)),
+            Tok::Close => Err("if: `)` inattesa".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defined_variables() {
+        state::set_shell_var("cond_test_set", "");
+        assert_eq!(eval("$?cond_test_set"), Ok(true));
+        assert_eq!(eval("! $?cond_test_unset"), Ok(true));
+        assert_eq!(eval("$?cond_test_set && $?cond_test_unset"), Ok(false));
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(eval("abc == abc"), Ok(true));
+        assert_eq!(eval("\"a b\" != 'a b'"), Ok(false));
+        assert_eq!(eval("main.rs =~ *.rs"), Ok(true));
+        assert_eq!(eval("10 > 9 && (2 <= 1 || 3 >= 3)"), Ok(true));
+        assert!(eval("a < 2").is_err());
+    }
+
+    #[test]
+    fn file_tests() {
+        let dir = std::env::temp_dir();
+        let dir = dir.to_string_lossy();
+        assert_eq!(eval(&format!("-d {}", dir)), Ok(true));
+        assert_eq!(eval(&format!("-f {}", dir)), Ok(false));
+        assert_eq!(eval("-e /grsh/non/esiste"), Ok(false));
+        assert_eq!(eval("-r /grsh/non/esiste"), Ok(false));
+    }
+
+    #[test]
+    fn permissions_follow_access() {
+        assert_eq!(eval("-r /"), Ok(true));
+        assert_eq!(eval("-x /"), Ok(true));
+        // `/` ha i bit di scrittura ma per un utente normale non è scrivibile
+        assert_eq!(eval("-w /"), Ok(access("/", AccessFlags::W_OK).is_ok()));
+    }
+
+    #[test]
+    fn malformed_expressions() {
+        assert!(eval("(a == a").is_err());
+        assert!(eval("a ==").is_err());
+        assert!(eval("a b").is_err());
+    }
+}
@@ -1,31 +1,306 @@
-use std::fs;
-use std::path::PathBuf;
 use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock, RwLockReadGuard};
+use std::time::SystemTime;
+use lazy_static::lazy_static;
+use nu_ansi_term::{Color, Style};
+use serde::{Deserialize, Deserializer};
+use toml::Table;
+use crate::shell::{alias, editmode, osstr, theme};
+
+// --- CONFIGURAZIONE DICHIARATIVA: ~/.config/grsh/config.toml ---
+// Le impostazioni tipizzate stanno qui, `.grshrc` resta per gli script di avvio
+// (viene eseguito dopo, quindi `set history = 500` o `set editmode = vi` vincono
+// sui valori del file). Chiavi sconosciute, tipi sbagliati e colori non validi
+// vengono segnalati con la riga; in caso di errore restano i valori predefiniti.
+// Esempio completo in `config.toml.example`.
+//
+// Prima di `.grshrc` si eseguono gli script `*.grsh` di /etc/grsh/conf.d,
+// /usr/local/etc/grsh/conf.d (ports e Homebrew) e poi ~/.config/grsh/conf.d,
+// ciascuna directory in ordine lessicale (`10-team.grsh` prima di
+// `20-progetto.grsh`): lì vanno alias e ambiente condivisi. Il builtin
+// `list-config` mostra i file letti davvero, `grsh --list-config` quelli che
+// verrebbero letti all'avvio.
 
+// Configurazione di sistema, comune a tutti gli utenti: prima quella del sistema
+// base, poi quella dei pacchetti installati sotto /usr/local
+const SYSTEM_DIRS: &[&str] = &["/etc/grsh", "/usr/local/etc/grsh"];
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
 pub struct GrshConfig {
+    pub history: HistoryConfig,
+    pub editor: EditorConfig,
+    pub completion: CompletionConfig,
+    pub colors: Colors,
+    pub ai: AiConfig,
+    pub redirect: RedirectConfig,
+    /// `[aliases]` ll = "ls -la"
     pub aliases: HashMap<String, String>,
+    /// `[prompt]` resta una tabella: la interpreta `theme` sopra il tema scelto
+    pub prompt: Option<Table>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// Numero massimo di voci (`set history = N` ha la precedenza)
+    pub size: usize,
+    /// Database della cronologia, `~` ammesso
+    #[serde(deserialize_with = "path")]
+    pub path: PathBuf,
 }
 
-pub fn load_grshrc() -> GrshConfig {
-    let mut config = GrshConfig { aliases: HashMap::new() };
-    let mut path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
-    path.push(".grshrc");
-
-    if let Ok(content) = fs::read_to_string(path) {
-        for line in content.lines() {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with('#') { continue; }
-
-            // Supporto Alias: alias ll="ls -la"
-            if line.starts_with("alias ") {
-                let parts: Vec<&str> = line.trim_start_matches("alias ").splitn(2, '=').collect();
-                if parts.len() == 2 {
-                    let key = parts[0].trim();
-                    let value = parts[1].trim().trim_matches('"').trim_matches('\'');
-                    config.aliases.insert(key.to_string(), value.to_string());
-                }
-            }
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig { size: 10000, path: home().join(".grsh_history.db") }
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct EditorConfig {
+    /// `emacs` o `vi` (`set editmode` ha la precedenza)
+    pub mode: Option<editmode::Mode>,
+    /// Codice DECSCUSR 0-6 (`GRSH_CURSOR` ha la precedenza)
+    #[serde(deserialize_with = "cursor")]
+    pub cursor: Option<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompletionConfig {
+    /// `Doc<Tab>` trova anche `docs/` e `Documents/`
+    pub case_insensitive: bool,
+    /// Propone i file nascosti anche senza il `.` iniziale
+    pub hidden: bool,
+    /// Completa subito la parte comune prima di mostrare il menu (`set autolist`)
+    pub partial: bool,
+    /// Con una sola possibilità la inserisce senza aprire il menu
+    pub quick: bool,
+    /// Colonne del menu
+    pub columns: u16,
+}
+
+impl Default for CompletionConfig {
+    fn default() -> Self {
+        CompletionConfig { case_insensitive: false, hidden: true, partial: false, quick: false, columns: 4 }
+    }
+}
+
+/// Colori dell'evidenziazione della riga di comando e del suggerimento
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    #[serde(deserialize_with = "style")]
+    pub command: Style,
+    #[serde(deserialize_with = "style")]
+    pub unknown_command: Style,
+    #[serde(deserialize_with = "style")]
+    pub operator: Style,
+    #[serde(deserialize_with = "style")]
+    pub assignment: Style,
+    #[serde(deserialize_with = "style")]
+    pub flag: Style,
+    #[serde(deserialize_with = "style")]
+    pub directory: Style,
+    #[serde(deserialize_with = "style")]
+    pub file: Style,
+    /// Percorso che non esiste (ancora)
+    #[serde(deserialize_with = "style")]
+    pub missing_path: Style,
+    #[serde(deserialize_with = "style")]
+    pub text: Style,
+    #[serde(deserialize_with = "style")]
+    pub hint: Style,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors {
+            command: Color::Cyan.bold(),
+            unknown_command: Color::Red.bold(),
+            operator: Color::Yellow.normal(),
+            assignment: Color::Purple.normal(),
+            flag: Color::Yellow.normal(),
+            directory: Color::Blue.bold(),
+            file: Color::White.normal(),
+            missing_path: Color::Fixed(250).normal(),
+            text: Color::White.normal(),
+            hint: Color::Green.normal(),
         }
     }
-    config
+}
+
+/// Assistente per le righe che iniziano con `?`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AiConfig {
+    pub enabled: bool,
+    pub command: String,
+    /// Argomenti prima della domanda
+    pub args: Vec<String>,
+}
+
+impl Default for AiConfig {
+    fn default() -> Self {
+        AiConfig { enabled: true, command: "tgpt".to_string(), args: vec!["-s".to_string()] }
+    }
+}
+
+/// Redirezioni "intelligenti": `comando > file.pdf` passa da enscript e ps2pdf
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedirectConfig {
+    pub pdf: bool,
+}
+
+impl Default for RedirectConfig {
+    fn default() -> Self {
+        RedirectConfig { pdf: true }
+    }
+}
+
+fn home() -> PathBuf {
+    dirs::home_dir().unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn style<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Style, D::Error> {
+    theme::parse_style(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+fn path<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    Ok(match text.strip_prefix("~/") {
+        Some(rest) => home().join(rest),
+        None => PathBuf::from(osstr::decode(&text)),
+    })
+}
+
+fn cursor<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u8>, D::Error> {
+    match u8::deserialize(deserializer)? {
+        code @ 0..=6 => Ok(Some(code)),
+        code => Err(serde::de::Error::custom(format!("cursore non valido: {} (0-6)", code))),
+    }
+}
+
+lazy_static! {
+    static ref CONFIG: RwLock<GrshConfig> = RwLock::new(GrshConfig::default());
+
+    // File di avvio letti con la loro data di modifica, per `set autoreload`
+    static ref WATCHED: Mutex<Vec<(PathBuf, Option<SystemTime>)>> = Mutex::new(Vec::new());
+
+    // File eseguiti dall'ultimo caricamento, nell'ordine, per `list-config`
+    static ref LOADED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+}
+
+// Cresce a ogni caricamento: il REPL ricostruisce l'editor quando cambia
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+pub fn generation() -> usize {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Ricorda i file di avvio appena letti (anche quelli che non esistono ancora)
+pub fn watch(files: Vec<PathBuf>) {
+    *WATCHED.lock().unwrap() = files.into_iter().map(|f| { let m = modified(&f); (f, m) }).collect();
+}
+
+/// Registra i file effettivamente letti dall'ultimo caricamento
+pub fn set_loaded(files: Vec<PathBuf>) {
+    *LOADED.lock().unwrap() = files;
+}
+
+/// File letti dall'ultimo caricamento, nell'ordine in cui sono stati eseguiti
+pub fn loaded() -> Vec<PathBuf> {
+    LOADED.lock().unwrap().clone()
+}
+
+/// Vero se un file di avvio è stato creato, modificato o cancellato dall'ultimo caricamento
+pub fn changed() -> bool {
+    WATCHED.lock().unwrap().iter().any(|(file, time)| modified(file) != *time)
+}
+
+/// Configurazione corrente (valori predefiniti se il file manca o non è valido)
+pub fn get() -> RwLockReadGuard<'static, GrshConfig> {
+    CONFIG.read().unwrap()
+}
+
+/// Directory della configurazione: `$XDG_CONFIG_HOME/grsh` o `~/.config/grsh`
+pub fn dir() -> PathBuf {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home().join(".config"))
+        .join("grsh")
+}
+
+/// Directory `conf.d`: prima quelle di sistema, poi quella dell'utente
+pub fn conf_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = SYSTEM_DIRS.iter().map(|d| Path::new(d).join("conf.d")).collect();
+    dirs.push(dir().join("conf.d"));
+    dirs
+}
+
+/// Script `*.grsh` delle directory `conf.d`, nell'ordine in cui vanno eseguiti
+pub fn snippets() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for dir in conf_dirs() {
+        let Ok(entries) = fs::read_dir(&dir) else { continue; };
+        let mut found: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == "grsh") && p.is_file())
+            .collect();
+        found.sort();
+        files.extend(found);
+    }
+    files
+}
+
+fn parse(text: &str) -> Result<GrshConfig, String> {
+    let config: GrshConfig = toml::from_str(text).map_err(|e| e.to_string().trim_end().to_string())?;
+    if config.history.size == 0 {
+        return Err("history.size deve essere maggiore di 0".to_string());
+    }
+    if config.completion.columns == 0 {
+        return Err("completion.columns deve essere maggiore di 0".to_string());
+    }
+    if config.ai.enabled && config.ai.command.trim().is_empty() {
+        return Err("ai.command vuoto: indica un programma o usa enabled = false".to_string());
+    }
+    Ok(config)
+}
+
+/// config.toml
+pub fn file() -> PathBuf {
+    dir().join("config.toml")
+}
+
+/// Legge config.toml, applica tema e alias e restituisce gli errori da mostrare
+pub fn load() -> Vec<String> {
+    let file = file();
+    let mut errors = Vec::new();
+    let mut config = match fs::read_to_string(&file) {
+        Ok(text) => parse(&text).unwrap_or_else(|e| {
+            errors.push(format!("grsh: {}: {}", file.display(), e));
+            GrshConfig::default()
+        }),
+        Err(_) => GrshConfig::default(),
+    };
+    if let Err(e) = theme::apply(config.prompt.take()) {
+        errors.push(format!("grsh: {}: {}", file.display(), e));
+    }
+    for (name, value) in &config.aliases {
+        alias::add_alias(name, value);
+    }
+    *CONFIG.write().unwrap() = config;
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    errors
 }
@@ -0,0 +1,165 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::shell::{osstr, resolver, state};
+
+// --- CORREZIONE ORTOGRAFICA IN STILE TCSH ---
+//   set correct = cmd    corregge solo il nome del comando
+//   set correct = all    anche i percorsi negli argomenti e la destinazione di `cd`
+// Come in tcsh la correzione si propone dopo Invio con `CORRECT>` (in `repl.rs`),
+// non dentro l'editor: `e` rimette la riga corretta nell'editor.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Off,
+    Cmd,
+    All,
+}
+
+pub fn mode() -> Mode {
+    match state::get_var("correct").as_deref() {
+        Some("cmd") => Mode::Cmd,
+        Some("all") => Mode::All,
+        _ => Mode::Off,
+    }
+}
+
+// Tastiera QWERTY: due tasti sono adiacenti se stanno vicini nella stessa riga
+// o in quella sopra/sotto
+const KEYBOARD: [&str; 4] = ["1234567890-", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+fn key_position(c: char) -> Option<(i32, i32)> {
+    let c = c.to_ascii_lowercase();
+    KEYBOARD.iter().enumerate().find_map(|(row, keys)| {
+        keys.find(c).map(|col| (row as i32, col as i32))
+    })
+}
+
+fn adjacent(a: char, b: char) -> bool {
+    match (key_position(a), key_position(b)) {
+        (Some((ra, ca)), Some((rb, cb))) => (ra - rb).abs() <= 1 && (ca - cb).abs() <= 1,
+        _ => false,
+    }
+}
+
+/// Distanza tra quanto digitato e un candidato: Damerau-Levenshtein, con i tasti
+/// vicini sulla tastiera che pesano la metà (`lz` è più probabile di `lq` per `ls`)
+fn score(typed: &str, candidate: &str) -> f64 {
+    let base = strsim::damerau_levenshtein(typed, candidate) as f64;
+    let near = if typed.chars().count() == candidate.chars().count() {
+        typed.chars().zip(candidate.chars()).filter(|&(a, b)| a != b && adjacent(a, b)).count()
+    } else {
+        0
+    };
+    base - 0.5 * near as f64
+}
+
+/// Il candidato più vicino a `typed`, se abbastanza simile
+pub fn closest(typed: &str, candidates: impl IntoIterator<Item = String>) -> Option<String> {
+    // Parole corte: al massimo un errore; lunghe: fino a due
+    let max = if typed.chars().count() <= 4 { 1.0 } else { 2.0 };
+    let mut best: Option<(String, f64, f64)> = None;
+    for candidate in candidates {
+        if candidate == typed { return None; }
+        let s = score(typed, &candidate);
+        if s > max { continue; }
+        // A parità di distanza vince il prefisso più lungo in comune (Jaro-Winkler)
+        let jw = strsim::jaro_winkler(typed, &candidate);
+        if best.as_ref().is_none_or(|(_, bs, bjw)| s < *bs || (s == *bs && jw > *bjw)) {
+            best = Some((candidate, s, jw));
+        }
+    }
+    best.map(|(c, _, _)| c)
+}
+
+/// Comando conosciuto più simile a `name`
+pub fn closest_command(name: &str) -> Option<String> {
+    closest(name, resolver::command_names())
+}
+
+// Solo le parole semplici vengono corrette: niente virgolette, escape o glob
+fn is_plain(word: &str) -> bool {
+    !word.is_empty()
+        && word.chars().all(|c| c.is_alphanumeric() || "._-/~+@%,:".contains(c))
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some(rest) => {
+            let home = dirs::home_dir().unwrap_or_default();
+            PathBuf::from(format!("{}{}", osstr::encode(home.as_os_str()), rest))
+        }
+        None => PathBuf::from(osstr::decode(path)),
+    }
+}
+
+/// Corregge componente per componente un percorso inesistente
+fn correct_path(word: &str, dirs_only: bool) -> Option<String> {
+    if expand_home(word).exists() { return None; }
+
+    let components: Vec<&str> = word.split('/').collect();
+    let mut fixed: Vec<String> = Vec::new();
+    for (i, component) in components.iter().enumerate() {
+        let last = i == components.len() - 1;
+        let so_far = fixed.join("/");
+        let candidate = if i == 0 { component.to_string() } else { format!("{}/{}", so_far, component) };
+        if component.is_empty() || *component == "." || *component == ".." || *component == "~"
+            || expand_home(&candidate).exists()
+        {
+            fixed.push(component.to_string());
+            continue;
+        }
+
+        let parent = if i == 0 { PathBuf::from(".") } else if so_far.is_empty() { PathBuf::from("/") } else { expand_home(&so_far) };
+        let want_dirs = dirs_only || !last;
+        let names = fs::read_dir(&parent).ok()?
+            .flatten()
+            .filter(|e| !want_dirs || Path::new(&e.path()).is_dir())
+            .map(|e| osstr::encode(&e.file_name()));
+        fixed.push(closest(component, names)?);
+    }
+    let fixed = fixed.join("/");
+    (fixed != word).then_some(fixed)
+}
+
+/// Riga corretta secondo `mode`, oppure `None` se non c'è nulla da correggere
+pub fn correct_line(line: &str, mode: Mode) -> Option<String> {
+    if mode == Mode::Off { return None; }
+
+    let mut out = String::with_capacity(line.len());
+    let mut changed = false;
+    let mut expect_command = true;
+    let mut command = String::new();
+
+    for piece in line.split_inclusive([' ', '\t']) {
+        let word = piece.trim_end_matches([' ', '\t']);
+        let sep = &piece[word.len()..];
+        let mut replacement = None;
+
+        if matches!(word, "|" | "|&" | "&&" | "||" | ";") {
+            expect_command = true;
+        } else if word.is_empty() {
+            // Spazi multipli
+        } else if expect_command {
+            if !resolver::is_assignment(word) {
+                expect_command = false;
+                command = word.to_string();
+                if is_plain(word) && !word.contains('/') && !resolver::is_known(word) {
+                    replacement = closest_command(word);
+                }
+                if let Some(fixed) = &replacement { command = fixed.clone(); }
+            }
+        } else if mode == Mode::All && is_plain(word) && !word.starts_with('-') {
+            let is_cd = command == "cd";
+            if is_cd || word.contains('/') {
+                replacement = correct_path(word, is_cd);
+            }
+        }
+
+        match replacement {
+            Some(fixed) => { out.push_str(&fixed); changed = true; }
+            None => out.push_str(word),
+        }
+        out.push_str(sep);
+    }
+    changed.then_some(out)
+}
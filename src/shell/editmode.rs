@@ -0,0 +1,90 @@
+use crossterm::cursor::SetCursorStyle;
+use reedline::{
+    default_emacs_keybindings, default_vi_insert_keybindings, default_vi_normal_keybindings,
+    CursorConfig, EditMode, Emacs, Vi,
+};
+use serde::Deserialize;
+use crate::shell::{config, keybind, state};
+
+// --- MODALITÀ DI EDITING: EMACS (DEFAULT) O VI ---
+//   set editmode = vi    (o `bindkey -v`)
+//   set editmode = emacs (o `bindkey -e`)
+// oppure `[editor] mode = "vi"` in config.toml
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Emacs,
+    Vi,
+}
+
+/// Modalità da `set editmode`, altrimenti `editor.mode` di config.toml
+pub fn mode() -> Mode {
+    match state::get_var("editmode").as_deref() {
+        Some("vi") => Mode::Vi,
+        Some(_) => Mode::Emacs,
+        None => config::get().editor.mode.unwrap_or(Mode::Emacs),
+    }
+}
+
+/// Forma del cursore scelta con `GRSH_CURSOR` o `editor.cursor` (codici DECSCUSR, 0 = non toccarlo)
+pub fn preferred_cursor() -> u8 {
+    state::get_var("GRSH_CURSOR")
+        .and_then(|v| v.parse().ok())
+        .or(config::get().editor.cursor)
+        .unwrap_or(2)
+}
+
+// In vi il cursore segue la modalità: blocco in normal, barra in insert.
+// Un `GRSH_CURSOR` dispari (lampeggiante) rende lampeggianti entrambi.
+fn vi_cursor(preferred: u8, insert: bool) -> u8 {
+    let blinking = preferred % 2 == 1;
+    match (insert, blinking) {
+        (true, true) => 5,
+        (true, false) => 6,
+        (false, true) => 1,
+        (false, false) => 2,
+    }
+}
+
+/// Codice del cursore da usare al prompt (in vi si parte in insert)
+pub fn prompt_cursor() -> u8 {
+    let preferred = preferred_cursor();
+    match mode() {
+        Mode::Emacs => preferred,
+        Mode::Vi if preferred == 0 => 0,
+        Mode::Vi => vi_cursor(preferred, true),
+    }
+}
+
+fn cursor_style(code: u8) -> Option<SetCursorStyle> {
+    match code {
+        1 => Some(SetCursorStyle::BlinkingBlock),
+        2 => Some(SetCursorStyle::SteadyBlock),
+        3 => Some(SetCursorStyle::BlinkingUnderScore),
+        4 => Some(SetCursorStyle::SteadyUnderScore),
+        5 => Some(SetCursorStyle::BlinkingBar),
+        6 => Some(SetCursorStyle::SteadyBar),
+        _ => None,
+    }
+}
+
+/// Cursori per reedline, che li cambia da solo quando si passa da insert a normal
+pub fn cursor_config() -> CursorConfig {
+    let preferred = preferred_cursor();
+    let vi = |insert| if preferred == 0 { None } else { cursor_style(vi_cursor(preferred, insert)) };
+    CursorConfig {
+        vi_insert: vi(true),
+        vi_normal: vi(false),
+        emacs: cursor_style(preferred),
+    }
+}
+
+/// Modalità di editing per l'editor, secondo `mode()`, con i tasti di `bindkey`
+pub fn edit_mode() -> Box<dyn EditMode> {
+    let inner: Box<dyn EditMode> = match mode() {
+        Mode::Emacs => Box::new(Emacs::new(default_emacs_keybindings())),
+        Mode::Vi => Box::new(Vi::new(default_vi_insert_keybindings(), default_vi_normal_keybindings())),
+    };
+    Box::new(keybind::BoundMode::new(inner))
+}
@@ -1,38 +1,13 @@
 use std::process::{Command, Stdio, Child};
 use std::fs::{OpenOptions, File};
-use crate::shell::{alias, state};
-use glob::glob;
+use std::ffi::OsString;
+use crate::shell::{alias, config, glob, lexer, osstr, resolver, state};
 use std::os::unix::process::CommandExt;
 
 use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
 use nix::unistd::{self, Pid};
 use nix::sys::wait::{waitpid, WaitStatus, WaitPidFlag};
 
-// --- Funzione smart_split ---
-fn smart_split(line: &str) -> Vec<String> {
-    let mut args = Vec::new();
-    let mut current = String::new();
-    let mut in_double_quotes = false;
-    let mut in_single_quotes = false;
-    for c in line.chars() {
-        match c {
-            '"' if !in_single_quotes => in_double_quotes = !in_double_quotes,
-            '\'' if !in_double_quotes => in_single_quotes = !in_single_quotes,
-            ' ' if !in_double_quotes && !in_single_quotes => {
-                if !current.is_empty() {
-                    args.push(current.clone());
-                    current.clear();
-                }
-            }
-            _ => current.push(c),
-        }
-    }
-    if !current.is_empty() {
-        args.push(current);
-    }
-    args
-}
-
 // --- Funzione per integrazione main.rs ---
 pub fn execute_with_args(program: &str, args: &[&str]) -> bool {
     let mut cmd = Command::new(program);
@@ -43,79 +18,146 @@ pub fn execute_with_args(program: &str, args: &[&str]) -> bool {
     }
 }
 
+// Redirezioni di un comando: `>` e `>>` (stdout e stderr) e `<`
+#[derive(Debug, Default, PartialEq)]
+struct Redirects {
+    output: Option<(String, bool)>,
+    input: Option<String>,
+}
+
+enum Token {
+    Word(String),
+    Op(&'static str),
+}
+
+// Separa le redirezioni dalle parole del comando (già divise da `smart_split`), in
+// qualsiasi ordine: l'operatore può stare da solo (`> out`) o attaccato (`>out`,
+// `cat<in`); se quotato o protetto con `\` resta testo
+fn take_redirects(words: Vec<String>) -> Result<(Vec<String>, Redirects), String> {
+    let mut tokens = Vec::new();
+    for word in words {
+        let mut current = String::new();
+        let mut chars = word.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    current.push(c);
+                    current.extend(chars.next());
+                }
+                '>' | '<' => {
+                    if !current.is_empty() { tokens.push(Token::Word(std::mem::take(&mut current))); }
+                    let op = match c {
+                        '<' => "<",
+                        _ if chars.next_if_eq(&'>').is_some() => ">>",
+                        _ => ">",
+                    };
+                    tokens.push(Token::Op(op));
+                }
+                _ => current.push(c),
+            }
+        }
+        if !current.is_empty() { tokens.push(Token::Word(current)); }
+    }
+
+    let mut parts = Vec::new();
+    let mut redirects = Redirects::default();
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(word) => parts.push(word),
+            Token::Op(op) => {
+                let Some(Token::Word(target)) = tokens.next() else {
+                    return Err(format!("redirezione senza file dopo `{}`", op));
+                };
+                let file = redirect_target(&target)?;
+                match op {
+                    "<" => redirects.input = Some(file),
+                    _ => redirects.output = Some((file, op == ">>")),
+                }
+            }
+        }
+    }
+    Ok((parts, redirects))
+}
+
+// Destinazione di una redirezione: si espande come le parole del comando (tilde,
+// graffe, glob; le variabili le ha già sostituite `run_line`) e deve dare un solo file
+fn redirect_target(word: &str) -> Result<String, String> {
+    match glob::expand_word(word, &glob::GlobOptions::from_shell()) {
+        Ok(mut files) if files.len() == 1 => Ok(files.remove(0)),
+        Ok(_) => Err(format!("redirezione ambigua: {}", osstr::display(&glob::unescape(word)))),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 // --- Funzione execute principale ---
 pub fn execute(line: &str) -> bool {
-    if line.contains(" && ") {
-        let parts: Vec<&str> = line.splitn(2, " && ").collect();
-        return if execute(parts[0]) { execute(parts[1]) } else { false };
+    if let Some(i) = lexer::find_unquoted(line, " && ") {
+        return if execute(&line[..i]) { execute(&line[i + 4..]) } else { false };
     }
 
-    let commands_chunks: Vec<&str> = line.split('|').collect();
+    let commands_chunks = lexer::split_unquoted(line, '|');
     let mut previous_child: Option<Child> = None;
     let mut last_status = true;
 
     for (i, cmd_chunk) in commands_chunks.iter().enumerate() {
         let is_last = i == commands_chunks.len() - 1;
-        let mut current_chunk = cmd_chunk.trim().to_string();
-        let mut file_out_redirect: Option<(String, bool)> = None;
-        let mut file_in_redirect: Option<String> = None;
-
-        if current_chunk.contains('<') {
-            let parts: Vec<&str> = current_chunk.split('<').collect();
-            if parts.len() == 2 {
-                file_in_redirect = Some(parts[1].trim().to_string());
-                current_chunk = parts[0].trim().to_string();
-            }
-        }
+        let expanded_chunk = alias::expand_aliases(cmd_chunk.trim());
+        let (raw_parts, redirects) = match take_redirects(lexer::smart_split(&expanded_chunk)) {
+            Ok(parsed) => parsed,
+            Err(e) => { eprintln!("grsh: {}", e); return false; }
+        };
+        if raw_parts.is_empty() { continue; }
 
-        if is_last {
-            if current_chunk.contains(">>") {
-                let parts: Vec<&str> = current_chunk.split(">>").collect();
-                if parts.len() == 2 {
-                    file_out_redirect = Some((parts[1].trim().to_string(), true));
-                    current_chunk = parts[0].trim().to_string();
-                }
-            } else if current_chunk.contains('>') {
-                let parts: Vec<&str> = current_chunk.split('>').collect();
-                if parts.len() == 2 {
-                    file_out_redirect = Some((parts[1].trim().to_string(), false));
-                    current_chunk = parts[0].trim().to_string();
-                }
-            }
+        // Assegnazioni iniziali: `VAR=x cmd` le passa solo a cmd, da sole impostano la variabile
+        let assignments = raw_parts.iter().take_while(|w| resolver::is_assignment(w)).count();
+        let (env_words, raw_parts) = raw_parts.split_at(assignments);
+        let env_vars: Vec<(String, String)> = env_words.iter()
+            .filter_map(|w| w.split_once('='))
+            .map(|(name, value)| (name.to_string(), glob::unescape(value)))
+            .collect();
+        if raw_parts.is_empty() {
+            for (name, value) in &env_vars { state::set_var(name, value); }
+            continue;
         }
 
-        let expanded_chunk = resolve_single_command_alias(&current_chunk);
-        let raw_parts = smart_split(&expanded_chunk);
-        if raw_parts.is_empty() { continue; }
-
-        let parts = expand_globs(raw_parts.iter().map(|s| s.as_str()).collect());
+        let parts = match expand_globs(raw_parts.iter().map(|s| s.as_str()).collect()) {
+            Ok(parts) if !parts.is_empty() => parts,
+            Ok(_) => continue,
+            Err(e) => {
+                eprintln!("grsh: {}", e);
+                return false;
+            }
+        };
         let program = &parts[0];
         let args = &parts[1..];
 
-        if let Some((ref filename, _)) = file_out_redirect {
-            if filename.to_lowercase().ends_with(".pdf") {
+        if let Some((ref filename, _)) = redirects.output {
+            if is_last && filename.to_lowercase().ends_with(".pdf") && config::get().redirect.pdf {
                 return execute_as_pdf(&parts, filename, previous_child);
             }
         }
 
-        let stdin = if let Some(ref in_file) = file_in_redirect {
-            if let Ok(f) = File::open(in_file) {
-                Stdio::from(f)
-            } else {
-                eprintln!("grsh: impossibile aprire file input: {}", in_file);
-                Stdio::null()
+        let stdin = if let Some(ref in_file) = redirects.input {
+            match File::open(osstr::decode(in_file)) {
+                Ok(f) => Stdio::from(f),
+                Err(e) => {
+                    eprintln!("grsh: {}: {}", osstr::display(in_file), e);
+                    return false;
+                }
             }
         } else {
+            // Se il comando precedente scriveva su file, alla pipe non arriva niente
             previous_child.take().map_or(Stdio::inherit(), |child| {
-                Stdio::from(child.stdout.expect("Failed to open stdout"))
+                child.stdout.map_or(Stdio::null(), Stdio::from)
             })
         };
 
         let (stdout, stderr);
-        if let Some((ref filename, append)) = file_out_redirect {
+        if let Some((ref filename, append)) = redirects.output {
             let file_result = OpenOptions::new()
                 .create(true).write(true).append(append).truncate(!append)
-                .open(filename);
+                .open(osstr::decode(filename));
             match file_result {
                 Ok(f) => {
                     let f2 = f.try_clone().expect("Clone file handle failed");
@@ -123,9 +165,8 @@ pub fn execute(line: &str) -> bool {
                     stderr = Stdio::from(f2);
                 },
                 Err(e) => {
-                    eprintln!("grsh: errore file: {}", e);
-                    stdout = Stdio::inherit();
-                    stderr = Stdio::inherit();
+                    eprintln!("grsh: {}: {}", osstr::display(filename), e);
+                    return false;
                 }
             }
         } else if !is_last {
@@ -138,6 +179,7 @@ pub fn execute(line: &str) -> bool {
 
         let mut cmd = Command::new(program);
         cmd.args(args).stdin(stdin).stdout(stdout).stderr(stderr);
+        cmd.envs(env_vars.iter().map(|(name, value)| (name, osstr::decode(value))));
 
         unsafe {
             cmd.pre_exec(|| {
@@ -160,15 +202,20 @@ pub fn execute(line: &str) -> bool {
                     loop {
                         match waitpid(child_pid, Some(WaitPidFlag::WUNTRACED)) {
                             Ok(WaitStatus::Stopped(_, _)) => {
-                                let full_command = parts.join(" ");
+                                let full_command = parts.iter()
+                                    .map(|p| p.to_string_lossy())
+                                    .collect::<Vec<_>>()
+                                    .join(" ");
                                 state::add_job(child_pid.as_raw(), full_command);
                                 break;
                             }
                             Ok(WaitStatus::Exited(_, status)) => {
+                                state::set_child_status(status);
                                 last_status = status == 0;
                                 break;
                             }
-                            Ok(WaitStatus::Signaled(_, _, _)) => {
+                            Ok(WaitStatus::Signaled(_, signal, _)) => {
+                                state::set_child_status(128 + signal as i32);
                                 last_status = false;
                                 break;
                             }
@@ -187,8 +234,9 @@ pub fn execute(line: &str) -> bool {
                 }
             }
             Err(_) => {
-                eprintln!("grsh: command not found: {}", program);
+                eprintln!("grsh: command not found: {}", osstr::display(&osstr::encode(program)));
                 previous_child = None;
+                state::set_child_status(127);
                 last_status = false;
                 break;
             }
@@ -197,35 +245,31 @@ pub fn execute(line: &str) -> bool {
     last_status
 }
 
-fn expand_globs(parts: Vec<&str>) -> Vec<String> {
+/// Espande le parole mantenendo la codifica interna di `osstr` (usato dai built-in)
+pub fn expand_words(parts: Vec<&str>) -> Result<Vec<String>, glob::ExpandError> {
+    let opts = glob::GlobOptions::from_shell();
     let mut expanded = Vec::new();
     for part in parts {
-        if (part.contains('*') || part.contains('?')) && !part.starts_with('\'') {
-            if let Ok(paths) = glob(part) {
-                let mut found = false;
-                for entry in paths.filter_map(Result::ok) {
-                    expanded.push(entry.display().to_string());
-                    found = true;
-                }
-                if !found { expanded.push(part.to_string()); }
-            } else { expanded.push(part.to_string()); }
-        } else {
-            expanded.push(part.trim_matches(|c| c == '\'' || c == '"').to_string());
-        }
+        expanded.extend(glob::expand_word(part, &opts)?);
     }
-    expanded
+    Ok(expanded)
+}
+
+/// Espande le parole e le riporta ai byte esatti da passare a `Command::args`
+pub fn expand_globs(parts: Vec<&str>) -> Result<Vec<OsString>, glob::ExpandError> {
+    Ok(expand_words(parts)?.iter().map(|w| osstr::decode(w)).collect())
 }
 
-fn execute_as_pdf(parts: &[String], output_pdf: &str, prev_child: Option<Child>) -> bool {
+fn execute_as_pdf(parts: &[OsString], output_pdf: &str, prev_child: Option<Child>) -> bool {
     let stdin = prev_child.map_or(Stdio::inherit(), |child| {
         Stdio::from(child.stdout.expect("Pipe error"))
     });
 
     if let Ok(child1) = Command::new(&parts[0]).args(&parts[1..]).stdin(stdin).stdout(Stdio::piped()).spawn() {
         if let Ok(child2) = Command::new("enscript").args(["-p", "-", "-q"]).stdin(Stdio::from(child1.stdout.unwrap())).stdout(Stdio::piped()).spawn() {
-            if let Ok(mut child3) = Command::new("ps2pdf").args(["-", output_pdf]).stdin(Stdio::from(child2.stdout.unwrap())).spawn() {
+            if let Ok(mut child3) = Command::new("ps2pdf").arg("-").arg(osstr::decode(output_pdf)).stdin(Stdio::from(child2.stdout.unwrap())).spawn() {
                 let _ = child3.wait();
-                println!("grsh: PDF generato con successo: {}", output_pdf);
+                println!("grsh: PDF generato con successo: {}", osstr::display(output_pdf));
                 return true;
             }
         }
@@ -234,14 +278,42 @@ fn execute_as_pdf(parts: &[String], output_pdf: &str, prev_child: Option<Child>)
     false
 }
 
-fn resolve_single_command_alias(cmd_part: &str) -> String {
-    let parts: Vec<&str> = cmd_part.split_whitespace().collect();
-    if parts.is_empty() { return cmd_part.to_string(); }
-    let resolved = alias::resolve_alias(parts[0]);
-    if resolved != parts[0] {
-        let clean_resolved = resolved.trim_matches(|c| c == '\'' || c == '"');
-        if parts.len() > 1 { format!("{} {}", clean_resolved, parts[1..].join(" ")) } else { clean_resolved.to_string() }
-    } else {
-        cmd_part.to_string()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> (Vec<String>, Redirects) {
+        take_redirects(lexer::smart_split(line)).unwrap()
+    }
+
+    #[test]
+    fn redirects_in_any_order() {
+        let expected = (
+            vec!["cat".to_string()],
+            Redirects { output: Some(("out.txt".into(), false)), input: Some("in.txt".into()) },
+        );
+        assert_eq!(parse("cat > out.txt < in.txt"), expected);
+        assert_eq!(parse("cat < in.txt > out.txt"), expected);
+    }
+
+    #[test]
+    fn attached_redirects() {
+        let (parts, redirects) = parse("sort<in.txt -r >>log.txt");
+        assert_eq!(parts, ["sort", "-r"]);
+        assert_eq!(redirects.input.as_deref(), Some("in.txt"));
+        assert_eq!(redirects.output, Some(("log.txt".to_string(), true)));
+    }
+
+    #[test]
+    fn quoted_operators_stay_words() {
+        let (parts, redirects) = parse(r#"echo ">" \< 'a>b'"#);
+        assert_eq!(parts.iter().map(|p| glob::unescape(p)).collect::<Vec<_>>(), ["echo", ">", "<", "a>b"]);
+        assert_eq!(redirects, Redirects::default());
+    }
+
+    #[test]
+    fn redirect_without_target() {
+        assert!(take_redirects(lexer::smart_split("cat <")).is_err());
+        assert!(take_redirects(lexer::smart_split("cat > > out")).is_err());
     }
 }
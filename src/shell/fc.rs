@@ -0,0 +1,202 @@
+use std::env;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use lazy_static::lazy_static;
+use reedline::HistoryItem;
+use crate::shell::history;
+
+// --- MODIFICA DEI COMANDI NELL'EDITOR: `fc` E CTRL+X CTRL+E ---
+//   fc                  modifica ed esegue l'ultimo comando
+//   fc 12 15 / fc git   modifica ed esegue un intervallo o l'ultimo comando che inizia per `git`
+//   fc -l [da [a]]      elenca (-n senza numeri, -r al contrario)
+//   fc -s [old=new] [c] riesegue subito, con una sostituzione facoltativa
+//   fc -e nano ...      usa un editor diverso (`-e -` equivale a `-s`)
+
+/// Editor dei comandi: `$VISUAL`, poi `$EDITOR`, altrimenti vi
+fn editor_name() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+// `EDITOR="code -w"`: il primo campo è il programma, il resto sono argomenti
+fn editor_process(name: &str) -> Command {
+    let mut parts = name.split_whitespace();
+    let mut command = Command::new(parts.next().unwrap_or("vi"));
+    command.args(parts);
+    command
+}
+
+lazy_static! {
+    // Directory 0700 creata alla prima modifica: in /tmp un altro utente potrebbe
+    // preparare un symlink al posto di un file dal nome prevedibile, qui dentro no
+    static ref TEMP_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+// `mkdir` fallisce se il nome esiste già (anche come symlink): riproviamo con un altro
+fn create_private_dir() -> io::Result<PathBuf> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    let mut last_error = io::Error::from(io::ErrorKind::AlreadyExists);
+    for attempt in 0..100u32 {
+        let dir = env::temp_dir().join(format!("grsh-{}-{:08x}", std::process::id(), nanos.wrapping_add(attempt)));
+        match DirBuilder::new().mode(0o700).create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => last_error = e,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(last_error)
+}
+
+fn temp_file(purpose: &str) -> io::Result<PathBuf> {
+    let mut dir = TEMP_DIR.lock().unwrap();
+    if dir.is_none() { *dir = Some(create_private_dir()?); }
+    Ok(dir.as_ref().unwrap().join(format!("{}.grsh", purpose)))
+}
+
+/// Rimuove i file temporanei della shell (all'uscita)
+pub fn cleanup() {
+    if let Some(dir) = TEMP_DIR.lock().unwrap().take() {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+/// Editor e file temporaneo per Ctrl+X Ctrl+E (il testo torna nella riga di comando);
+/// `None` se non si riesce a creare la directory temporanea
+pub fn buffer_editor() -> Option<(Command, PathBuf)> {
+    Some((editor_process(&editor_name()), temp_file("edit").ok()?))
+}
+
+// Numero dell'ultimo comando, senza l'invocazione di `fc` appena registrata
+fn newest() -> usize {
+    let count = history::count();
+    let last = history::range(count, count);
+    let is_fc = last.first().is_some_and(|(_, h)| h.command_line.split_whitespace().next() == Some("fc"));
+    count - usize::from(is_fc)
+}
+
+// Numero per `12`, `-2` (relativo) o `git` (prefisso)
+fn find(newest: usize, spec: &str) -> Result<usize, String> {
+    let found = match spec.parse::<i64>() {
+        Ok(n) if n < 0 => (newest + 1).checked_sub(n.unsigned_abs() as usize).filter(|&n| n > 0),
+        Ok(n) => usize::try_from(n).ok().filter(|&n| n > 0 && n <= newest),
+        Err(_) => {
+            // Due voci: la più recente può essere l'invocazione di `fc`
+            let search = history::Search { text: Some(history::Text::Prefix(spec.to_string())), ..Default::default() };
+            history::latest(search, Some(2)).into_iter().rev().map(|(n, _)| n).find(|&n| n <= newest)
+        }
+    };
+    found.ok_or_else(|| format!("grsh: fc: {}: evento non trovato", spec))
+}
+
+// Intervallo di numeri `da`..=`a`, più `true` se scritto al contrario.
+// Senza argomenti `fc -l` mostra gli ultimi 16 comandi, `fc` modifica solo l'ultimo.
+fn range(newest: usize, first: Option<&str>, last: Option<&str>, listing: bool) -> Result<(usize, usize, bool), String> {
+    if newest == 0 {
+        return Err("grsh: fc: cronologia vuota".to_string());
+    }
+    let from = match first {
+        Some(spec) => find(newest, spec)?,
+        None if listing => newest.saturating_sub(15).max(1),
+        None => newest,
+    };
+    let to = match last {
+        Some(spec) => find(newest, spec)?,
+        None if listing => newest,
+        None => from,
+    };
+    Ok((from.min(to), from.max(to), from > to))
+}
+
+/// Esegue `fc`: restituisce i comandi da eseguire, `None` se non c'è nulla da eseguire
+pub fn fc(args: &[&str]) -> Result<Option<String>, String> {
+    let (mut list, mut numbers, mut reverse, mut substitute) = (false, true, false, false);
+    let mut editor = None;
+    let mut operands = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(&arg) = iter.next() {
+        // `-3` è un evento, non un'opzione
+        let is_flag = arg.len() > 1 && arg.starts_with('-') && !arg[1..].starts_with(|c: char| c.is_ascii_digit());
+        if !is_flag || !operands.is_empty() {
+            operands.push(arg);
+            continue;
+        }
+        for flag in arg[1..].chars() {
+            match flag {
+                'l' => list = true,
+                'n' => numbers = false,
+                'r' => reverse = true,
+                's' => substitute = true,
+                'e' => match iter.next() {
+                    Some(&"-") => substitute = true,
+                    Some(name) => editor = Some(name.to_string()),
+                    None => return Err("grsh: fc: -e richiede il nome dell'editor".to_string()),
+                },
+                other => return Err(format!("grsh: fc: opzione sconosciuta: -{}", other)),
+            }
+        }
+    }
+
+    let newest = newest();
+
+    if substitute {
+        let (old_new, spec) = match operands.first() {
+            Some(first) if first.contains('=') => (Some(*first), operands.get(1).copied()),
+            first => (None, first.copied()),
+        };
+        let number = find(newest, spec.unwrap_or("-1"))?;
+        let Some((_, item)) = history::range(number, number).pop() else {
+            return Err(format!("grsh: fc: {}: evento non trovato", number));
+        };
+        let mut command = item.command_line;
+        if let Some((old, new)) = old_new.and_then(|s| s.split_once('=')) {
+            command = command.replace(old, new);
+        }
+        return Ok(Some(command));
+    }
+
+    if list {
+        let (from, to, reversed) = range(newest, operands.first().copied(), operands.get(1).copied(), true)?;
+        let mut selected: Vec<(usize, HistoryItem)> = history::range(from, to);
+        if reverse != reversed { selected.reverse(); }
+        for (number, item) in selected {
+            if numbers {
+                println!("{:>6}  {}", number, item.command_line);
+            } else {
+                println!("        {}", item.command_line);
+            }
+        }
+        return Ok(None);
+    }
+
+    let (from, to, reversed) = range(newest, operands.first().copied(), operands.get(1).copied(), false)?;
+    let entries = history::range(from, to);
+    let mut commands: Vec<&str> = entries.iter().map(|(_, h)| h.command_line.as_str()).collect();
+    if reverse != reversed { commands.reverse(); }
+
+    let file = temp_file("fc").map_err(|e| format!("grsh: fc: directory temporanea: {}", e))?;
+    let _ = fs::remove_file(&file);
+    OpenOptions::new().write(true).create_new(true).mode(0o600).open(&file)
+        .and_then(|mut f| f.write_all(format!("{}\n", commands.join("\n")).as_bytes()))
+        .map_err(|e| format!("grsh: fc: {}: {}", file.display(), e))?;
+    let status = editor_process(&editor.unwrap_or_else(editor_name)).arg(&file).status();
+    let edited = fs::read_to_string(&file);
+    let _ = fs::remove_file(&file);
+
+    match status {
+        Ok(s) if s.success() => {}
+        Ok(_) => return Err("grsh: fc: l'editor è terminato con errore, comandi non eseguiti".to_string()),
+        Err(e) => return Err(format!("grsh: fc: impossibile avviare l'editor: {}", e)),
+    }
+    let edited = edited.map_err(|e| format!("grsh: fc: {}", e))?;
+    let edited = edited.trim();
+    Ok((!edited.is_empty()).then(|| edited.to_string()))
+}
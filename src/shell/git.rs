@@ -0,0 +1,311 @@
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use lazy_static::lazy_static;
+
+// --- STATO GIT PER IL PROMPT ---
+// Repository, ramo, operazioni in corso e stash si leggono direttamente dai file
+// in `.git`, senza processi. I conteggi delle modifiche e ahead/behind richiedono
+// `git status`, lento nei repository grandi: gira in un thread, il prompt lo
+// aspetta al massimo `WAIT` una sola volta per epoca (reedline ridisegna il prompt a
+// ogni tasto) e altrimenti mostra l'ultimo valore noto.
+//
+// Limite noto: il prompt NON si ridisegna da solo quando `git status` termina.
+// Reedline 0.34 resta bloccato in `event::read()`; un SIGWINCH lo sveglia ma il
+// ridimensionamento non causa un ridisegno, e `external_printer` (anche in 0.43)
+// ridisegna solo dopo aver stampato almeno una riga sopra il prompt. Il valore
+// aggiornato compare al primo ridisegno (un tasto qualsiasi) o al prompt successivo.
+
+// Attesa massima del prompt per `git status`
+const WAIT: Duration = Duration::from_millis(80);
+// Oltre questo tempo `git status` viene interrotto
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Dove punta HEAD
+#[derive(Debug, Clone, PartialEq)]
+pub enum Head {
+    Branch(String),
+    /// HEAD staccato su un tag
+    Tag(String),
+    /// HEAD staccato: hash abbreviato
+    Detached(String),
+}
+
+/// Risultato di `git status`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Status {
+    pub ahead: usize,
+    pub behind: usize,
+    pub staged: usize,
+    pub unstaged: usize,
+    pub untracked: usize,
+    pub conflicts: usize,
+    /// Tag che punta a HEAD, cercato solo se HEAD è staccato
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitInfo {
+    pub head: Head,
+    /// Operazione in corso: `REBASE 2/5`, `MERGING`, `BISECTING`...
+    pub operation: Option<String>,
+    pub stash: usize,
+    /// `None` finché `git status` non ha risposto
+    pub status: Option<Status>,
+}
+
+// Repository trovato risalendo dalla directory corrente
+struct Repo {
+    workdir: PathBuf,
+    // Directory git di questo worktree (`.git`, o quella indicata dal file `.git`)
+    git_dir: PathBuf,
+    // Directory con refs e log condivisi tra i worktree
+    common_dir: PathBuf,
+}
+
+// Chiave di una lettura: repository ed epoca (cresce dopo ogni comando)
+type Key = (PathBuf, usize);
+
+#[derive(Default)]
+struct StatusCache {
+    key: Option<Key>,
+    status: Option<Status>,
+    pending: Option<Key>,
+    // Richiesta per cui il prompt ha già atteso `WAIT`
+    waited: Option<Key>,
+}
+
+lazy_static! {
+    static ref CACHE: Mutex<StatusCache> = Mutex::new(StatusCache::default());
+}
+
+static EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+/// Un comando può aver cambiato i file: il prossimo prompt rilegge lo stato
+pub fn invalidate() {
+    EPOCH.fetch_add(1, Ordering::Relaxed);
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+// `.git` è una directory, oppure (worktree e submodule) un file `gitdir: <percorso>`
+fn find_repo() -> Option<Repo> {
+    let cwd = env::current_dir().ok()?;
+    for dir in cwd.ancestors() {
+        let dot_git = dir.join(".git");
+        let git_dir = if dot_git.is_dir() {
+            dot_git
+        } else if dot_git.is_file() {
+            let content = read_trimmed(&dot_git)?;
+            dir.join(content.strip_prefix("gitdir:")?.trim())
+        } else {
+            continue;
+        };
+        let common_dir = read_trimmed(&git_dir.join("commondir"))
+            .map(|c| git_dir.join(c))
+            .unwrap_or_else(|| git_dir.clone());
+        return Some(Repo { workdir: dir.to_path_buf(), git_dir, common_dir });
+    }
+    None
+}
+
+// `ref: refs/heads/main` → ramo; HEAD staccato → hash abbreviato
+fn read_head(repo: &Repo) -> Option<Head> {
+    let head = read_trimmed(&repo.git_dir.join("HEAD"))?;
+    Some(match head.strip_prefix("ref: ") {
+        Some(reference) => Head::Branch(reference.strip_prefix("refs/heads/").unwrap_or(reference).to_string()),
+        None => Head::Detached(head.chars().take(7).collect()),
+    })
+}
+
+// Operazione in corso, come la mostra `git-prompt.sh`. Durante un rebase HEAD è
+// staccato: restituiamo anche il ramo che si sta ribasando.
+fn read_operation(repo: &Repo) -> (Option<String>, Option<String>) {
+    let dir = &repo.git_dir;
+    let progress = |sub: &str, current: &str, total: &str| -> String {
+        match (read_trimmed(&dir.join(sub).join(current)), read_trimmed(&dir.join(sub).join(total))) {
+            (Some(n), Some(m)) => format!(" {}/{}", n, m),
+            _ => String::new(),
+        }
+    };
+    let branch_of = |sub: &str| {
+        read_trimmed(&dir.join(sub).join("head-name"))
+            .map(|h| h.strip_prefix("refs/heads/").unwrap_or(&h).to_string())
+    };
+
+    if dir.join("rebase-merge").is_dir() {
+        let name = if dir.join("rebase-merge/interactive").exists() { "REBASE-i" } else { "REBASE-m" };
+        return (Some(format!("{}{}", name, progress("rebase-merge", "msgnum", "end"))), branch_of("rebase-merge"));
+    }
+    if dir.join("rebase-apply").is_dir() {
+        let name = if dir.join("rebase-apply/rebasing").exists() {
+            "REBASE"
+        } else if dir.join("rebase-apply/applying").exists() {
+            "AM"
+        } else {
+            "AM/REBASE"
+        };
+        return (Some(format!("{}{}", name, progress("rebase-apply", "next", "last"))), branch_of("rebase-apply"));
+    }
+    let operation = [
+        ("MERGE_HEAD", "MERGING"),
+        ("CHERRY_PICK_HEAD", "CHERRY-PICKING"),
+        ("REVERT_HEAD", "REVERTING"),
+        ("BISECT_LOG", "BISECTING"),
+    ]
+    .iter()
+    .find(|(file, _)| dir.join(file).exists())
+    .map(|(_, name)| name.to_string());
+    (operation, None)
+}
+
+// Ogni `git stash` aggiunge una riga al reflog di refs/stash
+fn read_stash(repo: &Repo) -> usize {
+    fs::read_to_string(repo.common_dir.join("logs/refs/stash"))
+        .map(|log| log.lines().count())
+        .unwrap_or(0)
+}
+
+// Esegue git con un tempo massimo; `None` se fallisce o non risponde
+fn run_git(workdir: &Path, args: &[&str]) -> Option<String> {
+    let mut child = Command::new("git")
+        .arg("-C").arg(workdir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let mut stdout = child.stdout.take()?;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = String::new();
+        let _ = tx.send(stdout.read_to_string(&mut output).map(|_| output).ok());
+    });
+    let output = rx.recv_timeout(TIMEOUT).ok().flatten();
+    if output.is_none() { let _ = child.kill(); }
+    let success = child.wait().is_ok_and(|s| s.success());
+    output.filter(|_| success)
+}
+
+// Formato `--porcelain=v2`: righe `# branch.ab +1 -2`, `1 XY ...`, `2 XY ...`, `u ...`, `? ...`
+fn parse_status(output: &str) -> Status {
+    let mut status = Status::default();
+    for line in output.lines() {
+        if let Some(ab) = line.strip_prefix("# branch.ab ") {
+            let mut counts = ab.split_whitespace().map(|n| n.trim_start_matches(['+', '-']).parse().unwrap_or(0));
+            status.ahead = counts.next().unwrap_or(0);
+            status.behind = counts.next().unwrap_or(0);
+            continue;
+        }
+        let mut fields = line.splitn(3, ' ');
+        match (fields.next(), fields.next()) {
+            (Some("1" | "2"), Some(xy)) => {
+                let mut xy = xy.chars();
+                if xy.next().is_some_and(|x| x != '.') { status.staged += 1; }
+                if xy.next().is_some_and(|y| y != '.') { status.unstaged += 1; }
+            }
+            (Some("u"), _) => status.conflicts += 1,
+            (Some("?"), _) => status.untracked += 1,
+            _ => {}
+        }
+    }
+    status
+}
+
+fn read_status(workdir: &Path, detached: bool) -> Option<Status> {
+    let output = run_git(workdir, &["status", "--porcelain=v2", "--branch", "--untracked-files=normal"])?;
+    let mut status = parse_status(&output);
+    if detached {
+        status.tag = run_git(workdir, &["describe", "--tags", "--exact-match", "HEAD"])
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty());
+    }
+    Some(status)
+}
+
+fn start_status(key: Key, detached: bool) {
+    thread::spawn(move || {
+        let status = read_status(&key.0, detached);
+        let mut cache = CACHE.lock().unwrap();
+        // Un risultato vecchio non sostituisce quello di una richiesta più recente
+        if cache.pending.as_ref() == Some(&key) {
+            cache.pending = None;
+            cache.key = Some(key);
+            cache.status = status;
+        }
+    });
+}
+
+fn status(workdir: &Path, detached: bool) -> Option<Status> {
+    let key: Key = (workdir.to_path_buf(), EPOCH.load(Ordering::Relaxed));
+    {
+        let mut cache = CACHE.lock().unwrap();
+        if cache.key.as_ref() == Some(&key) { return cache.status.clone(); }
+        if cache.pending.as_ref() != Some(&key) {
+            cache.pending = Some(key.clone());
+            start_status(key.clone(), detached);
+        }
+        if cache.waited.as_ref() == Some(&key) { return last_known(&cache, workdir); }
+        cache.waited = Some(key.clone());
+    }
+
+    // Nei repository piccoli la risposta arriva quasi subito
+    let deadline = Instant::now() + WAIT;
+    while Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(5));
+        let cache = CACHE.lock().unwrap();
+        if cache.key.as_ref() == Some(&key) { return cache.status.clone(); }
+    }
+
+    last_known(&CACHE.lock().unwrap(), workdir)
+}
+
+// Intanto l'ultimo valore noto per lo stesso repository
+fn last_known(cache: &StatusCache, workdir: &Path) -> Option<Status> {
+    cache.key.as_ref().filter(|(w, _)| w == workdir).and(cache.status.clone())
+}
+
+/// Stato del repository che contiene la directory corrente
+pub fn info() -> Option<GitInfo> {
+    let repo = find_repo()?;
+    let mut head = read_head(&repo)?;
+    let (operation, rebasing) = read_operation(&repo);
+    if let Some(branch) = rebasing { head = Head::Branch(branch); }
+
+    let status = status(&repo.workdir, matches!(head, Head::Detached(_)));
+    if let (Head::Detached(_), Some(tag)) = (&head, status.as_ref().and_then(|s| s.tag.clone())) {
+        head = Head::Tag(tag);
+    }
+    Some(GitInfo { head, operation, stash: read_stash(&repo), status })
+}
+
+impl GitInfo {
+    /// Forma compatta per il prompt: `main ↑1 ↓2 +3 ~4 ?5 ✖1 ⚑2 |REBASE-i 2/5`
+    pub fn summary(&self) -> String {
+        let mut out = match &self.head {
+            Head::Branch(name) => name.clone(),
+            Head::Tag(tag) => format!("tag:{}", tag),
+            Head::Detached(hash) => format!("({})", hash),
+        };
+        if let Some(s) = &self.status {
+            for (count, symbol) in [
+                (s.ahead, "↑"), (s.behind, "↓"), (s.staged, "+"), (s.unstaged, "~"),
+                (s.untracked, "?"), (s.conflicts, "✖"),
+            ] {
+                if count > 0 { out.push_str(&format!(" {}{}", symbol, count)); }
+            }
+        }
+        if self.stash > 0 { out.push_str(&format!(" ⚑{}", self.stash)); }
+        if let Some(op) = &self.operation { out.push_str(&format!(" |{}", op)); }
+        out
+    }
+}
@@ -0,0 +1,730 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use crate::shell::{globqual, osstr, state};
+
+// Caratteri che il tokenizer protegge con `\` quando compaiono tra virgolette,
+// così da non essere interpretati dal motore di globbing (né come redirezioni).
+pub const GLOB_SPECIAL: &[char] = &['\\', '*', '?', '[', ']', '{', '}', '(', ')', ',', '|', '!', '@', '+', '~', '<', '>'];
+
+// --- OPZIONI DI GLOBBING (set dotglob, set nullglob, ...) ---
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GlobOptions {
+    pub dotglob: bool,
+    pub nullglob: bool,
+    pub failglob: bool,
+    pub nocaseglob: bool,
+    pub noglob: bool,
+}
+
+impl GlobOptions {
+    /// Legge le opzioni correnti dalle variabili della shell
+    pub fn from_shell() -> Self {
+        GlobOptions {
+            dotglob: state::is_set("dotglob"),
+            nullglob: state::is_set("nullglob"),
+            failglob: state::is_set("failglob"),
+            nocaseglob: state::is_set("nocaseglob"),
+            noglob: state::is_set("noglob"),
+        }
+    }
+}
+
+// Parole massime prodotte dalla brace expansion di una sola parola: `{1..100000000000}`
+// esaurirebbe la memoria
+pub const MAX_BRACE_WORDS: usize = 100_000;
+
+/// Errore di espansione: blocca il comando
+#[derive(Debug)]
+pub enum ExpandError {
+    /// Con `failglob` attivo, pattern senza risultati
+    NoMatch(String),
+    /// Brace expansion oltre `MAX_BRACE_WORDS` parole
+    TooManyWords(String),
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExpandError::NoMatch(pattern) => write!(f, "nessuna corrispondenza: {}", osstr::display(pattern)),
+            ExpandError::TooManyWords(word) => write!(
+                f, "espansione troppo grande: {} (massimo {} parole)", osstr::display(word), MAX_BRACE_WORDS
+            ),
+        }
+    }
+}
+
+// --- ESCAPE ---
+
+/// Protegge tutti i caratteri speciali di `s` (usato per testo quotato o già espanso)
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if GLOB_SPECIAL.contains(&c) { out.push('\\'); }
+        out.push(c);
+    }
+    out
+}
+
+/// Rimuove gli escape, restituendo il testo letterale della parola
+pub fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(n) = chars.next() { out.push(n); }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Vero se il pattern contiene metacaratteri di globbing non protetti
+pub fn has_magic(pattern: &str) -> bool {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '*' | '?' | '[' => return true,
+            '+' | '@' | '!' if chars.get(i + 1) == Some(&'(') => return true,
+            _ => {}
+        }
+        i += 1;
+    }
+    false
+}
+
+// --- BRACE EXPANSION ---
+
+/// Espande `{a,b}` e le sequenze `{1..10}`, `{a..e}`, `{01..10..2}` (anche annidate).
+/// L'ordine dei risultati è quello in cui compaiono le alternative.
+pub fn expand_braces(word: &str) -> Result<Vec<String>, ExpandError> {
+    let too_many = || ExpandError::TooManyWords(unescape(word));
+    let chars: Vec<char> = word.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => { i += 2; continue; }
+            '{' => {
+                if let Some(close) = find_brace_close(&chars, i) {
+                    let inner: String = chars[i + 1..close].iter().collect();
+                    let commas = split_top_level_commas(&inner);
+                    let alternatives = if commas.len() > 1 {
+                        Some(commas)
+                    } else {
+                        expand_sequence(&inner).map_err(|_| too_many())?
+                    };
+                    if let Some(alts) = alternatives {
+                        let prefix: String = chars[..i].iter().collect();
+                        let suffix: String = chars[close + 1..].iter().collect();
+                        let mut out = Vec::new();
+                        for alt in alts {
+                            out.extend(expand_braces(&format!("{}{}{}", prefix, alt, suffix)).map_err(|_| too_many())?);
+                            if out.len() > MAX_BRACE_WORDS { return Err(too_many()); }
+                        }
+                        return Ok(out);
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    Ok(vec![word.to_string()])
+}
+
+fn find_brace_close(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = open;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 { return Some(i); }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn split_top_level_commas(inner: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                current.push(c);
+                if let Some(n) = chars.next() { current.push(n); }
+            }
+            '{' => { depth += 1; current.push(c); }
+            '}' => { depth -= 1; current.push(c); }
+            ',' if depth == 0 => parts.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    parts.push(current);
+    parts
+}
+
+// `Ok(None)` se `inner` non è una sequenza (la parola resta com'è),
+// `Err` se produrrebbe più di `MAX_BRACE_WORDS` parole
+fn expand_sequence(inner: &str) -> Result<Option<Vec<String>>, ExpandError> {
+    let fields: Vec<&str> = inner.split("..").collect();
+    if fields.len() < 2 || fields.len() > 3 { return Ok(None); }
+
+    let step: i64 = match fields.get(2) {
+        Some(s) => match s.parse::<i64>().ok().and_then(i64::checked_abs) {
+            Some(step) => step.max(1),
+            None => return Ok(None),
+        },
+        None => 1,
+    };
+
+    // Sequenza numerica, con eventuale zero-padding ({01..10})
+    let (start, end, width, letters) = match (fields[0].parse::<i64>(), fields[1].parse::<i64>()) {
+        (Ok(start), Ok(end)) => {
+            let padded = |s: &str| s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0');
+            let width = if padded(fields[0]) || padded(fields[1]) {
+                fields[0].len().max(fields[1].len())
+            } else { 0 };
+            (start, end, width, false)
+        }
+        // Sequenza di caratteri ({a..e})
+        _ => match (single_char(fields[0]), single_char(fields[1])) {
+            (Some(a), Some(b)) if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => (a as i64, b as i64, 0, true),
+            _ => return Ok(None),
+        },
+    };
+
+    // Il numero di elementi si calcola prima di generarli
+    let count = (end as i128 - start as i128).unsigned_abs() / step as u128 + 1;
+    if count > MAX_BRACE_WORDS as u128 {
+        return Err(ExpandError::TooManyWords(inner.to_string()));
+    }
+
+    let delta = if start <= end { step } else { -step };
+    let mut out = Vec::with_capacity(count as usize);
+    let mut n = start;
+    loop {
+        if letters {
+            out.push(escape(&char::from(n as u8).to_string()));
+        } else {
+            out.push(format!("{:0width$}", n, width = width));
+        }
+        match n.checked_add(delta) {
+            Some(next) if (delta > 0 && next <= end) || (delta < 0 && next >= end) => n = next,
+            _ => break,
+        }
+    }
+    Ok(Some(out))
+}
+
+fn single_char(s: &str) -> Option<char> {
+    let mut it = s.chars();
+    let c = it.next()?;
+    if it.next().is_some() { None } else { Some(c) }
+}
+
+// --- TILDE ---
+
+/// Espande `~` e `~utente` all'inizio della parola
+fn expand_tilde(word: &str) -> String {
+    if !word.starts_with('~') { return word.to_string(); }
+    let end = word.find('/').unwrap_or(word.len());
+    let user = &word[1..end];
+
+    let home = if user.is_empty() {
+        dirs::home_dir().map(|h| osstr::encode(h.as_os_str()))
+    } else {
+        nix::unistd::User::from_name(user)
+            .ok()
+            .flatten()
+            .map(|u| osstr::encode(u.dir.as_os_str()))
+    };
+
+    match home {
+        Some(h) => format!("{}{}", escape(&h), &word[end..]),
+        None => word.to_string(),
+    }
+}
+
+// --- PATTERN MATCHING (fnmatch + extglob) ---
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Named(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExtKind { ZeroOrOne, ZeroOrMore, OneOrMore, ExactlyOne, Not }
+
+#[derive(Debug, Clone)]
+enum Tok {
+    Lit(char),
+    AnyChar,
+    AnyString,
+    Class(bool, Vec<ClassItem>),
+    Ext(ExtKind, Vec<Vec<Tok>>),
+}
+
+/// Pattern compilato per un singolo componente di percorso
+pub struct Pattern {
+    toks: Vec<Tok>,
+    nocase: bool,
+    literal_dot: bool,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str, nocase: bool) -> Self {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut pos = 0;
+        let toks = parse_toks(&chars, &mut pos, false);
+        let literal_dot = matches!(toks.first(), Some(Tok::Lit('.')));
+        Pattern { toks, nocase, literal_dot }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let text: Vec<char> = name.chars().collect();
+        let mut matcher = Matcher { text: &text, nocase: self.nocase, memo: HashMap::new() };
+        matcher.toks(&self.toks, 0, text.len())
+    }
+}
+
+fn parse_toks(chars: &[char], pos: &mut usize, in_group: bool) -> Vec<Tok> {
+    let mut toks = Vec::new();
+    while *pos < chars.len() {
+        let c = chars[*pos];
+        if in_group && (c == '|' || c == ')') { break; }
+        match c {
+            '\\' => {
+                *pos += 1;
+                if let Some(&n) = chars.get(*pos) { toks.push(Tok::Lit(n)); }
+                *pos += 1;
+            }
+            '?' | '*' | '+' | '@' | '!' if chars.get(*pos + 1) == Some(&'(') => {
+                let kind = match c {
+                    '?' => ExtKind::ZeroOrOne,
+                    '*' => ExtKind::ZeroOrMore,
+                    '+' => ExtKind::OneOrMore,
+                    '@' => ExtKind::ExactlyOne,
+                    _ => ExtKind::Not,
+                };
+                let start = *pos;
+                *pos += 2;
+                let mut alts = vec![parse_toks(chars, pos, true)];
+                while chars.get(*pos) == Some(&'|') {
+                    *pos += 1;
+                    alts.push(parse_toks(chars, pos, true));
+                }
+                if chars.get(*pos) == Some(&')') {
+                    *pos += 1;
+                    toks.push(Tok::Ext(kind, alts));
+                } else {
+                    // Parentesi non chiusa: trattiamo l'apertura come testo
+                    *pos = start + 1;
+                    toks.push(if c == '*' { Tok::AnyString } else if c == '?' { Tok::AnyChar } else { Tok::Lit(c) });
+                }
+            }
+            '?' => { toks.push(Tok::AnyChar); *pos += 1; }
+            '*' => {
+                if !matches!(toks.last(), Some(Tok::AnyString)) { toks.push(Tok::AnyString); }
+                *pos += 1;
+            }
+            '[' => {
+                match parse_class(chars, *pos) {
+                    Some((tok, next)) => { toks.push(tok); *pos = next; }
+                    None => { toks.push(Tok::Lit('[')); *pos += 1; }
+                }
+            }
+            _ => { toks.push(Tok::Lit(c)); *pos += 1; }
+        }
+    }
+    toks
+}
+
+fn parse_class(chars: &[char], open: usize) -> Option<(Tok, usize)> {
+    let mut i = open + 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated { i += 1; }
+
+    let mut items = Vec::new();
+    let mut first = true;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == ']' && !first {
+            return Some((Tok::Class(negated, items), i + 1));
+        }
+        first = false;
+
+        if c == '[' && chars.get(i + 1) == Some(&':') {
+            let rest: String = chars[i + 2..].iter().collect();
+            if let Some(end) = rest.find(":]") {
+                items.push(ClassItem::Named(rest[..end].to_string()));
+                i += 2 + rest[..end].chars().count() + 2;
+                continue;
+            }
+        }
+
+        let (lo, next) = if c == '\\' && i + 1 < chars.len() { (chars[i + 1], i + 2) } else { (c, i + 1) };
+        if chars.get(next) == Some(&'-') && chars.get(next + 1).is_some_and(|&n| n != ']') {
+            let (hi, after) = if chars[next + 1] == '\\' && next + 2 < chars.len() {
+                (chars[next + 2], next + 3)
+            } else {
+                (chars[next + 1], next + 2)
+            };
+            items.push(ClassItem::Range(lo, hi));
+            i = after;
+        } else {
+            items.push(ClassItem::Char(lo));
+            i = next;
+        }
+    }
+    None
+}
+
+fn class_matches(items: &[ClassItem], c: char, nocase: bool) -> bool {
+    let candidates: Vec<char> = if nocase {
+        c.to_lowercase().chain(c.to_uppercase()).collect()
+    } else {
+        vec![c]
+    };
+    candidates.iter().any(|&c| items.iter().any(|item| match item {
+        ClassItem::Char(x) => *x == c,
+        ClassItem::Range(lo, hi) => *lo <= c && c <= *hi,
+        ClassItem::Named(name) => match name.as_str() {
+            "alpha" => c.is_alphabetic(),
+            "digit" => c.is_ascii_digit(),
+            "alnum" => c.is_alphanumeric(),
+            "upper" => c.is_uppercase(),
+            "lower" => c.is_lowercase(),
+            "space" => c.is_whitespace(),
+            "punct" => c.is_ascii_punctuation(),
+            "xdigit" => c.is_ascii_hexdigit(),
+            "cntrl" => c.is_control(),
+            "print" => !c.is_control(),
+            "graph" => !c.is_control() && !c.is_whitespace(),
+            "blank" => c == ' ' || c == '\t',
+            _ => false,
+        },
+    }))
+}
+
+fn chars_eq(a: char, b: char, nocase: bool) -> bool {
+    a == b || (nocase && a.to_lowercase().eq(b.to_lowercase()))
+}
+
+// Il backtracking su `*` e sugli extglob è esponenziale nel caso peggiore
+// (`*a*a*a*b`, `+(a|aa)` su nomi lunghi): ogni sotto-pattern si prova al più
+// una volta per intervallo di testo. Le chiavi usano l'indirizzo delle slice di
+// token, stabili per tutta la durata del confronto.
+struct Matcher<'a> {
+    text: &'a [char],
+    nocase: bool,
+    memo: HashMap<(bool, usize, usize, usize, usize), bool>,
+}
+
+impl Matcher<'_> {
+    fn toks(&mut self, toks: &[Tok], start: usize, end: usize) -> bool {
+        let Some((tok, rest)) = toks.split_first() else { return start == end; };
+        let key = (false, toks.as_ptr() as usize, toks.len(), start, end);
+        if let Some(&hit) = self.memo.get(&key) { return hit; }
+        let result = match tok {
+            Tok::Lit(c) => start < end && chars_eq(*c, self.text[start], self.nocase) && self.toks(rest, start + 1, end),
+            Tok::AnyChar => start < end && self.toks(rest, start + 1, end),
+            Tok::Class(negated, items) => {
+                start < end
+                    && class_matches(items, self.text[start], self.nocase) != *negated
+                    && self.toks(rest, start + 1, end)
+            }
+            Tok::AnyString => (start..=end).any(|i| self.toks(rest, i, end)),
+            Tok::Ext(kind, alts) => (start..=end).any(|i| {
+                let ok = match kind {
+                    ExtKind::ExactlyOne => self.any(alts, start, i),
+                    ExtKind::ZeroOrOne => i == start || self.any(alts, start, i),
+                    ExtKind::ZeroOrMore => self.repeat(alts, start, i),
+                    ExtKind::OneOrMore => i > start && self.repeat(alts, start, i),
+                    ExtKind::Not => !self.any(alts, start, i),
+                };
+                ok && self.toks(rest, i, end)
+            }),
+        };
+        self.memo.insert(key, result);
+        result
+    }
+
+    fn any(&mut self, alts: &[Vec<Tok>], start: usize, end: usize) -> bool {
+        alts.iter().any(|alt| self.toks(alt, start, end))
+    }
+
+    fn repeat(&mut self, alts: &[Vec<Tok>], start: usize, end: usize) -> bool {
+        if start == end { return true; }
+        let key = (true, alts.as_ptr() as usize, alts.len(), start, end);
+        if let Some(&hit) = self.memo.get(&key) { return hit; }
+        let result = (start + 1..=end).any(|j| self.any(alts, start, j) && self.repeat(alts, j, end));
+        self.memo.insert(key, result);
+        result
+    }
+}
+
+// --- ESPANSIONE SUL FILESYSTEM ---
+
+fn split_components(pattern: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                current.push(c);
+                if let Some(n) = chars.next() { current.push(n); }
+            }
+            '/' => parts.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    parts.push(current);
+    parts
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else if prefix.ends_with('/') {
+        format!("{}{}", prefix, name)
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+// I percorsi interni sono nella codifica di `osstr`: li riportiamo ai byte reali
+fn dir_of(prefix: &str) -> PathBuf {
+    if prefix.is_empty() { PathBuf::from(".") } else { PathBuf::from(osstr::decode(prefix)) }
+}
+
+fn list_dir(prefix: &str, dotglob: bool) -> Vec<(String, bool)> {
+    let mut out = Vec::new();
+    if let Ok(entries) = fs::read_dir(dir_of(prefix)) {
+        for entry in entries.flatten() {
+            let name = osstr::encode(&entry.file_name());
+            if name.starts_with('.') && !dotglob { continue; }
+            // `file_type` non segue i symlink: serve per non ciclare dentro `**`
+            let is_real_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            out.push((name, is_real_dir));
+        }
+    }
+    out
+}
+
+/// Tutte le sottodirectory (prefisso incluso) per `**`, senza seguire i symlink
+fn walk_dirs(prefix: &str, dotglob: bool, out: &mut Vec<String>) {
+    out.push(prefix.to_string());
+    for (name, is_dir) in list_dir(prefix, dotglob) {
+        if is_dir { walk_dirs(&join(prefix, &name), dotglob, out); }
+    }
+}
+
+fn glob_path(pattern: &str, opts: &GlobOptions) -> Vec<String> {
+    let dirs_only = pattern.len() > 1 && pattern.ends_with('/');
+    let mut components = split_components(pattern);
+    let mut current: Vec<String> = if pattern.starts_with('/') {
+        components.remove(0);
+        vec!["/".to_string()]
+    } else {
+        vec![String::new()]
+    };
+    components.retain(|c| !c.is_empty());
+    let last = components.len().saturating_sub(1);
+
+    for (idx, comp) in components.iter().enumerate() {
+        let is_last = idx == last;
+        let mut next = Vec::new();
+
+        if comp == "**" {
+            for base in &current {
+                let mut dirs = Vec::new();
+                walk_dirs(base, opts.dotglob, &mut dirs);
+                if is_last {
+                    // `**` finale: tutto il contenuto ricorsivo, file compresi
+                    for d in dirs {
+                        for (name, _) in list_dir(&d, opts.dotglob) { next.push(join(&d, &name)); }
+                    }
+                } else {
+                    next.extend(dirs);
+                }
+            }
+        } else if !has_magic(comp) {
+            let literal = unescape(comp);
+            for base in &current {
+                let candidate = join(base, &literal);
+                if fs::symlink_metadata(dir_of(&candidate)).is_ok() { next.push(candidate); }
+            }
+        } else {
+            let pat = Pattern::new(comp, opts.nocaseglob);
+            let show_hidden = opts.dotglob || pat.literal_dot;
+            for base in &current {
+                for (name, _) in list_dir(base, show_hidden) {
+                    if pat.matches(&name) { next.push(join(base, &name)); }
+                }
+            }
+        }
+
+        if !is_last {
+            next.retain(|p| dir_of(p).is_dir());
+        }
+        current = next;
+        if current.is_empty() { break; }
+    }
+
+    if dirs_only {
+        current = current.into_iter()
+            .filter(|p| dir_of(p).is_dir())
+            .map(|p| if p.ends_with('/') { p } else { format!("{}/", p) })
+            .collect();
+    }
+
+    // Rimuove la directory corrente introdotta da un `**` finale vuoto
+    current.retain(|p| !p.is_empty());
+    // Ordinamento per byte, come sul filesystem
+    current.sort_by_key(|p| osstr::decode(p));
+    current.dedup();
+    current
+}
+
+/// Espande una parola (già in forma di pattern, con i caratteri quotati protetti):
+/// brace expansion, tilde, poi globbing con gli eventuali qualificatori.
+/// Restituisce le parole letterali risultanti (byte non UTF-8 codificati con `osstr`).
+pub fn expand_word(word: &str, opts: &GlobOptions) -> Result<Vec<String>, ExpandError> {
+    let mut out = Vec::new();
+    for alt in expand_braces(word)? {
+        let alt = expand_tilde(&alt);
+        if opts.noglob {
+            out.push(unescape(&alt));
+            continue;
+        }
+
+        // Qualificatori zsh finali: `*.rs(.m-1)`
+        let (pattern, quals) = match globqual::split(&alt) {
+            Some((pattern, quals)) => (pattern, Some(quals)),
+            None if has_magic(&alt) => (alt.clone(), None),
+            None => {
+                out.push(unescape(&alt));
+                continue;
+            }
+        };
+
+        let mut local = *opts;
+        local.dotglob |= quals.as_ref().is_some_and(|q| q.dotglob);
+
+        let mut matches = glob_path(&pattern, &local);
+        if let Some(q) = &quals {
+            matches = q.apply(matches);
+        }
+
+        if !matches.is_empty() {
+            out.extend(matches);
+        } else if quals.as_ref().is_some_and(|q| q.nullglob) {
+            continue;
+        } else if opts.failglob {
+            return Err(ExpandError::NoMatch(unescape(&alt)));
+        } else if !opts.nullglob {
+            out.push(unescape(&alt));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn braces(word: &str) -> Vec<String> {
+        expand_braces(word).unwrap()
+    }
+
+    #[test]
+    fn brace_sequences() {
+        assert_eq!(braces("{1..4}"), ["1", "2", "3", "4"]);
+        assert_eq!(braces("{4..1..2}"), ["4", "2"]);
+        assert_eq!(braces("{-2..1}"), ["-2", "-1", "0", "1"]);
+        assert_eq!(braces("{a..e..2}"), ["a", "c", "e"]);
+        assert_eq!(braces("{1..x}"), ["{1..x}"]);
+    }
+
+    #[test]
+    fn brace_padding() {
+        assert_eq!(braces("f{01..03}"), ["f01", "f02", "f03"]);
+        assert_eq!(braces("{8..010}"), ["008", "009", "010"]);
+    }
+
+    #[test]
+    fn nested_braces() {
+        assert_eq!(braces("a{b,c{1,2}}d"), ["abd", "ac1d", "ac2d"]);
+        assert_eq!(braces("{x,y}{1..2}"), ["x1", "x2", "y1", "y2"]);
+        assert_eq!(braces("\\{a,b\\}"), ["\\{a,b\\}"]);
+    }
+
+    #[test]
+    fn brace_limits() {
+        assert!(matches!(expand_braces("{1..100000000000}"), Err(ExpandError::TooManyWords(_))));
+        assert!(matches!(expand_braces("{1..1000}{1..1000}"), Err(ExpandError::TooManyWords(_))));
+        assert!(expand_braces("{-9223372036854775808..-9223372036854775807}").is_ok());
+        assert_eq!(braces("{9223372036854775806..9223372036854775807}").len(), 2);
+        assert_eq!(braces("{1..3..-9223372036854775808}"), ["{1..3..-9223372036854775808}"]);
+    }
+
+    #[test]
+    fn extglob_negation() {
+        let pattern = Pattern::new("!(*.rs|*.toml)", false);
+        assert!(pattern.matches("README.md"));
+        assert!(!pattern.matches("main.rs"));
+        assert!(!pattern.matches("Cargo.toml"));
+        assert!(Pattern::new("+(ab)c", false).matches("ababc"));
+        assert!(Pattern::new("@(x|y).txt", false).matches("y.txt"));
+    }
+
+    #[test]
+    fn pathological_patterns() {
+        let started = std::time::Instant::now();
+        let name = "a".repeat(200);
+        assert!(!Pattern::new("a*a*a*a*b", false).matches(&name));
+        assert!(!Pattern::new("*a*a*a*a*a*a*a*a*b", false).matches(&name));
+        assert!(!Pattern::new("+(a|aa)b", false).matches(&name));
+        assert!(!Pattern::new("*(*a|a*)c", false).matches(&name));
+        assert!(Pattern::new("*a*a*a*a*a*a*a*a", false).matches(&name));
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    #[test]
+    fn recursive_globstar() {
+        let root = std::env::temp_dir().join(format!("grsh-glob-test-{}", std::process::id()));
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        for file in ["top.rs", "a/mid.rs", "a/b/deep.rs", "a/b/note.txt"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let base = root.to_string_lossy().into_owned();
+        let mut found = expand_word(&format!("{}/**/*.rs", base), &GlobOptions::default()).unwrap();
+        found.sort();
+        let _ = fs::remove_dir_all(&root);
+        let expected: Vec<String> = ["a/b/deep.rs", "a/mid.rs", "top.rs"].iter().map(|f| format!("{}/{}", base, f)).collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn qualifiers_need_a_pattern() {
+        let opts = GlobOptions { failglob: true, ..GlobOptions::default() };
+        assert_eq!(expand_word("foo(x)", &opts).unwrap(), ["foo(x)"]);
+        assert_eq!(expand_word("/grsh/non/esiste(.)", &opts).unwrap(), ["/grsh/non/esiste(.)"]);
+        assert!(matches!(expand_word("/grsh/non/esiste/*(.)", &opts), Err(ExpandError::NoMatch(_))));
+    }
+}
@@ -0,0 +1,399 @@
+use std::fs::{self, Metadata};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::time::{SystemTime, UNIX_EPOCH};
+use nix::unistd::{self, Group, User};
+use crate::shell::{glob, osstr};
+
+// --- QUALIFICATORI IN STILE ZSH: **/*.rs(.m-1), *(om[1,5]) ---
+
+#[derive(Clone, Copy, Debug)]
+enum Cmp { Less, Equal, Greater }
+
+#[derive(Clone, Copy, Debug)]
+enum TimeField { Modified, Accessed, Changed }
+
+#[derive(Clone, Debug)]
+enum Test {
+    Dir,
+    File,
+    Symlink,
+    Socket,
+    Fifo,
+    Device,
+    Executable,
+    AnyBits(u32),
+    Mode(u32),
+    Uid(u32),
+    Gid(u32),
+    Size(Cmp, u64, u64),
+    Time(TimeField, Cmp, u64, u64),
+}
+
+#[derive(Clone, Debug)]
+struct Filter {
+    test: Test,
+    negate: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum SortKey { Name, Size, Time(TimeField) }
+
+/// Qualificatori già analizzati: filtri (in OR tra gruppi separati da `,`),
+/// ordinamento, intervallo `[n,m]` e opzioni locali `N`/`D`.
+#[derive(Clone, Debug, Default)]
+pub struct Qualifiers {
+    groups: Vec<Vec<Filter>>,
+    sort: Option<(SortKey, bool)>,
+    range: Option<(i64, i64)>,
+    pub nullglob: bool,
+    pub dotglob: bool,
+}
+
+/// Separa l'eventuale `(...)` finale dal pattern. Restituisce `None` se la parola
+/// non termina con dei qualificatori validi (es. un pattern esteso `@(a|b)`).
+pub fn split(word: &str) -> Option<(String, Qualifiers)> {
+    if !word.ends_with(')') || word.ends_with("\\)") { return None; }
+    let chars: Vec<char> = word.chars().collect();
+
+    let mut depth = 0;
+    let mut open = None;
+    for i in (0..chars.len()).rev() {
+        if i > 0 && chars[i - 1] == '\\' { continue; }
+        match chars[i] {
+            ')' => depth += 1,
+            '(' => {
+                depth -= 1;
+                if depth == 0 { open = Some(i); break; }
+            }
+            _ => {}
+        }
+    }
+    let open = open?;
+    // `?(..)`, `+(..)`, `@(..)`, `!(..)` sono pattern estesi; `*(..)` lo è solo se
+    // il contenuto non è una lista di qualificatori valida (zsh: `*(.)`, `*(/)`)
+    if open == 0 || matches!(chars[open - 1], '?' | '+' | '@' | '!' | '\\') { return None; }
+
+    // Senza caratteri jolly prima delle parentesi è una parola normale: `foo(x)`
+    let pattern: String = chars[..open].iter().collect();
+    if !glob::has_magic(&pattern) { return None; }
+
+    let spec: String = chars[open + 1..chars.len() - 1].iter().collect();
+    if spec.contains('|') { return None; }
+    let quals = parse(&spec)?;
+    Some((pattern, quals))
+}
+
+fn parse(spec: &str) -> Option<Qualifiers> {
+    let chars: Vec<char> = spec.chars().collect();
+    let mut q = Qualifiers::default();
+    let mut group = Vec::new();
+    let mut negate = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        let test = match c {
+            '/' => Test::Dir,
+            '.' => Test::File,
+            '@' => Test::Symlink,
+            '=' => Test::Socket,
+            'p' => Test::Fifo,
+            '%' => Test::Device,
+            '*' => Test::Executable,
+            'r' => Test::AnyBits(0o400),
+            'w' => Test::AnyBits(0o200),
+            'x' => Test::AnyBits(0o100),
+            'A' => Test::AnyBits(0o040),
+            'I' => Test::AnyBits(0o020),
+            'E' => Test::AnyBits(0o010),
+            'R' => Test::AnyBits(0o004),
+            'W' => Test::AnyBits(0o002),
+            'X' => Test::AnyBits(0o001),
+            's' => Test::AnyBits(0o4000),
+            'S' => Test::AnyBits(0o2000),
+            't' => Test::AnyBits(0o1000),
+            'U' => Test::Uid(unistd::geteuid().as_raw()),
+            'G' => Test::Gid(unistd::getegid().as_raw()),
+            'f' => {
+                let digits: String = chars[i..].iter().take_while(|c| c.is_digit(8)).collect();
+                i += digits.len();
+                Test::Mode(u32::from_str_radix(&digits, 8).ok()?)
+            }
+            'u' | 'g' => {
+                let (id, next) = parse_owner(&chars, i, c == 'u')?;
+                i = next;
+                if c == 'u' { Test::Uid(id) } else { Test::Gid(id) }
+            }
+            'L' => {
+                let unit = match chars.get(i) {
+                    Some('k') | Some('K') => { i += 1; 1024 }
+                    Some('m') | Some('M') => { i += 1; 1024 * 1024 }
+                    Some('g') | Some('G') => { i += 1; 1024 * 1024 * 1024 }
+                    Some('p') | Some('P') => { i += 1; 512 }
+                    _ => 1,
+                };
+                let (cmp, n, next) = parse_number(&chars, i)?;
+                i = next;
+                Test::Size(cmp, n, unit)
+            }
+            'm' | 'a' | 'c' => {
+                let field = time_field(c);
+                let unit = match chars.get(i) {
+                    Some('M') => { i += 1; 30 * 86400 }
+                    Some('w') => { i += 1; 7 * 86400 }
+                    Some('d') => { i += 1; 86400 }
+                    Some('h') => { i += 1; 3600 }
+                    Some('m') => { i += 1; 60 }
+                    Some('s') => { i += 1; 1 }
+                    _ => 86400,
+                };
+                let (cmp, n, next) = parse_number(&chars, i)?;
+                i = next;
+                Test::Time(field, cmp, n, unit)
+            }
+            'o' | 'O' => {
+                let key = match chars.get(i)? {
+                    'n' => SortKey::Name,
+                    'L' => SortKey::Size,
+                    k @ ('m' | 'a' | 'c') => SortKey::Time(time_field(*k)),
+                    _ => return None,
+                };
+                i += 1;
+                q.sort = Some((key, c == 'O'));
+                continue;
+            }
+            '[' => {
+                let end = chars[i..].iter().position(|&c| c == ']')? + i;
+                let inner: String = chars[i..end].iter().collect();
+                i = end + 1;
+                let (a, b) = match inner.split_once(',') {
+                    Some((a, b)) => (a.trim().parse().ok()?, b.trim().parse().ok()?),
+                    None => { let n = inner.trim().parse().ok()?; (n, n) }
+                };
+                q.range = Some((a, b));
+                continue;
+            }
+            '^' => { negate = !negate; continue; }
+            'N' => { q.nullglob = true; continue; }
+            'D' => { q.dotglob = true; continue; }
+            ',' => {
+                q.groups.push(std::mem::take(&mut group));
+                negate = false;
+                continue;
+            }
+            _ => return None,
+        };
+        group.push(Filter { test, negate });
+    }
+    q.groups.push(group);
+    Some(q)
+}
+
+fn time_field(c: char) -> TimeField {
+    match c {
+        'a' => TimeField::Accessed,
+        'c' => TimeField::Changed,
+        _ => TimeField::Modified,
+    }
+}
+
+fn parse_number(chars: &[char], mut i: usize) -> Option<(Cmp, u64, usize)> {
+    let cmp = match chars.get(i) {
+        Some('-') => { i += 1; Cmp::Less }
+        Some('+') => { i += 1; Cmp::Greater }
+        _ => Cmp::Equal,
+    };
+    let digits: String = chars[i..].iter().take_while(|c| c.is_ascii_digit()).collect();
+    let n = digits.parse().ok()?;
+    Some((cmp, n, i + digits.len()))
+}
+
+// `u0`, `u:root:` (qualsiasi delimitatore, come in zsh)
+fn parse_owner(chars: &[char], i: usize, user: bool) -> Option<(u32, usize)> {
+    let digits: String = chars[i..].iter().take_while(|c| c.is_ascii_digit()).collect();
+    if !digits.is_empty() {
+        return Some((digits.parse().ok()?, i + digits.len()));
+    }
+    let delim = *chars.get(i)?;
+    let close = match delim { '[' => ']', '{' => '}', '<' => '>', d => d };
+    let end = chars[i + 1..].iter().position(|&c| c == close)? + i + 1;
+    let name: String = chars[i + 1..end].iter().collect();
+    let id = if user {
+        User::from_name(&name).ok()??.uid.as_raw()
+    } else {
+        Group::from_name(&name).ok()??.gid.as_raw()
+    };
+    Some((id, end + 1))
+}
+
+fn compare(cmp: Cmp, value: u64, n: u64) -> bool {
+    match cmp {
+        Cmp::Less => value < n,
+        Cmp::Equal => value == n,
+        Cmp::Greater => value > n,
+    }
+}
+
+fn timestamp(meta: &Metadata, field: TimeField) -> i64 {
+    match field {
+        TimeField::Modified => meta.mtime(),
+        TimeField::Accessed => meta.atime(),
+        TimeField::Changed => meta.ctime(),
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+impl Filter {
+    fn matches(&self, link: &Metadata, meta: &Metadata) -> bool {
+        let ft = meta.file_type();
+        let ok = match &self.test {
+            Test::Dir => ft.is_dir(),
+            Test::File => ft.is_file(),
+            Test::Symlink => link.file_type().is_symlink(),
+            Test::Socket => ft.is_socket(),
+            Test::Fifo => ft.is_fifo(),
+            Test::Device => ft.is_block_device() || ft.is_char_device(),
+            Test::Executable => ft.is_file() && meta.mode() & 0o111 != 0,
+            Test::AnyBits(bits) => meta.mode() & bits != 0,
+            Test::Mode(mode) => meta.mode() & 0o7777 == *mode,
+            Test::Uid(uid) => meta.uid() == *uid,
+            Test::Gid(gid) => meta.gid() == *gid,
+            // Come zsh, la dimensione viene arrotondata per eccesso all'unità richiesta
+            Test::Size(cmp, n, unit) => compare(*cmp, meta.size().div_ceil(*unit), *n),
+            Test::Time(field, cmp, n, unit) => {
+                let age = (now() - timestamp(meta, *field)).max(0) as u64;
+                compare(*cmp, age / unit, *n)
+            }
+        };
+        ok != self.negate
+    }
+}
+
+impl Qualifiers {
+    /// Filtra, ordina e taglia i risultati di un glob
+    pub fn apply(&self, paths: Vec<String>) -> Vec<String> {
+        let mut entries: Vec<(String, Metadata)> = paths.into_iter()
+            .filter_map(|p| {
+                let path = osstr::decode(&p);
+                let link = fs::symlink_metadata(&path).ok()?;
+                // I test seguono i symlink, tranne `@`; un link rotto usa i propri metadati
+                let meta = fs::metadata(&path).unwrap_or_else(|_| link.clone());
+                let keep = self.groups.iter().any(|g| g.iter().all(|f| f.matches(&link, &meta)));
+                keep.then_some((p, meta))
+            })
+            .collect();
+
+        if let Some((key, reverse)) = self.sort {
+            entries.sort_by(|(pa, ma), (pb, mb)| {
+                let ord = match key {
+                    SortKey::Name => osstr::decode(pa).cmp(&osstr::decode(pb)),
+                    SortKey::Size => ma.size().cmp(&mb.size()),
+                    // `om` mette per primi i file più recenti
+                    SortKey::Time(field) => timestamp(mb, field).cmp(&timestamp(ma, field)),
+                };
+                if reverse { ord.reverse() } else { ord }
+            });
+        }
+
+        let mut out: Vec<String> = entries.into_iter().map(|(p, _)| p).collect();
+        if let Some((a, b)) = self.range {
+            let len = out.len() as i64;
+            let index = |n: i64| if n < 0 { len + n } else { n - 1 };
+            let (start, end) = (index(a).max(0), index(b).min(len - 1));
+            out = if start <= end && start < len {
+                out[start as usize..=end as usize].to_vec()
+            } else {
+                Vec::new()
+            };
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quals(spec: &str) -> Qualifiers {
+        parse(spec).unwrap_or_else(|| panic!("qualificatori non validi: {}", spec))
+    }
+
+    // Directory con `a` (3 byte), `b` (1), `c` (2) e la sottodirectory `d`
+    fn fixture() -> (std::path::PathBuf, Vec<String>) {
+        let dir = std::env::temp_dir().join(format!("grsh-globqual-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("d")).unwrap();
+        for (name, size) in [("a", 3), ("b", 1), ("c", 2)] {
+            fs::write(dir.join(name), "x".repeat(size)).unwrap();
+        }
+        let paths = ["a", "b", "c", "d"].iter().map(|n| dir.join(n).to_string_lossy().into_owned()).collect();
+        (dir, paths)
+    }
+
+    fn names(paths: Vec<String>) -> Vec<String> {
+        paths.iter().map(|p| p.rsplit('/').next().unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn split_needs_a_pattern_before_the_parentheses() {
+        assert!(split("foo(x)").is_none());
+        assert!(split("@(a|b)").is_none());
+        assert!(split("*(a|b)").is_none());
+        assert!(split("*.rs\\(.\\)").is_none());
+        let (pattern, q) = split("*(.)").unwrap();
+        assert_eq!(pattern, "*");
+        assert!(matches!(q.groups[..], [ref g] if matches!(g[..], [Filter { test: Test::File, negate: false }])));
+        assert_eq!(split("src/**/*.rs(om[1,3])").unwrap().0, "src/**/*.rs");
+    }
+
+    #[test]
+    fn parse_sort_keys() {
+        assert!(matches!(quals("on").sort, Some((SortKey::Name, false))));
+        assert!(matches!(quals("OL").sort, Some((SortKey::Size, true))));
+        assert!(matches!(quals("oa").sort, Some((SortKey::Time(TimeField::Accessed), false))));
+        assert!(parse("ox").is_none());
+        assert!(parse("o").is_none());
+    }
+
+    #[test]
+    fn parse_ranges() {
+        assert_eq!(quals("[1,5]").range, Some((1, 5)));
+        assert_eq!(quals("[-3,-1]").range, Some((-3, -1)));
+        assert_eq!(quals("[2]").range, Some((2, 2)));
+        assert!(parse("[1,x]").is_none());
+        assert!(parse("[1,2").is_none());
+    }
+
+    #[test]
+    fn parse_negation_and_groups() {
+        let q = quals("^/,@N");
+        assert!(q.nullglob);
+        assert_eq!(q.groups.len(), 2);
+        assert!(matches!(q.groups[0][..], [Filter { test: Test::Dir, negate: true }]));
+        // La negazione vale solo fino alla `,`
+        assert!(matches!(q.groups[1][..], [Filter { test: Test::Symlink, negate: false }]));
+        assert!(matches!(quals("^^.").groups[0][..], [Filter { test: Test::File, negate: false }]));
+        assert!(parse("Q").is_none());
+    }
+
+    #[test]
+    fn apply_filters_sorts_and_slices() {
+        let (dir, paths) = fixture();
+        assert_eq!(names(quals(".").apply(paths.clone())), ["a", "b", "c"]);
+        assert_eq!(names(quals("^.").apply(paths.clone())), ["d"]);
+        assert_eq!(names(quals("/,L1").apply(paths.clone())), ["b", "d"]);
+        assert_eq!(names(quals(".oL").apply(paths.clone())), ["b", "c", "a"]);
+        assert_eq!(names(quals(".oL[2,3]").apply(paths.clone())), ["c", "a"]);
+        assert_eq!(names(quals(".OL[-1]").apply(paths.clone())), ["b"]);
+        assert_eq!(names(quals(".on[-2,-1]").apply(paths.clone())), ["b", "c"]);
+        assert!(quals(".[5,6]").apply(paths.clone()).is_empty());
+        assert!(quals(".[-9,-8]").apply(paths.clone()).is_empty());
+        assert!(quals(".[3,1]").apply(paths.clone()).is_empty());
+        assert!(quals("=").apply(paths).is_empty());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,373 @@
+use crate::shell::lexer;
+
+// --- ESPANSIONE DELLA CRONOLOGIA IN STILE TCSH: !!, !$, !n, !str, ^old^new ---
+
+/// Riga ottenuta dall'espansione; con `:p` va solo stampata, non eseguita
+#[derive(Debug, Clone)]
+pub struct Expansion {
+    pub line: String,
+    pub print_only: bool,
+}
+
+/// Cronologia vista dall'espansione: eventi numerati da 1, dal più vecchio
+pub trait Events {
+    /// Evento numero `n`
+    fn nth(&self, n: usize) -> Option<String>;
+    /// `n`-esimo evento dalla fine (1 è il più recente)
+    fn nth_back(&self, n: usize) -> Option<String>;
+    /// Evento più recente che inizia con `prefix`
+    fn starting_with(&self, prefix: &str) -> Option<String>;
+    /// Evento più recente che contiene `text`
+    fn containing(&self, text: &str) -> Option<String>;
+}
+
+impl Events for Vec<String> {
+    fn nth(&self, n: usize) -> Option<String> {
+        self.get(n.checked_sub(1)?).cloned()
+    }
+
+    fn nth_back(&self, n: usize) -> Option<String> {
+        self.len().checked_sub(n).and_then(|i| self.get(i)).cloned()
+    }
+
+    fn starting_with(&self, prefix: &str) -> Option<String> {
+        self.iter().rev().find(|e| e.starts_with(prefix)).cloned()
+    }
+
+    fn containing(&self, text: &str) -> Option<String> {
+        self.iter().rev().find(|e| e.contains(text)).cloned()
+    }
+}
+
+/// Vero se la riga contiene (forse) riferimenti alla cronologia
+pub fn has_references(line: &str) -> bool {
+    line.starts_with('^') || line.contains('!')
+}
+
+/// Espande i riferimenti alla cronologia.
+/// Restituisce `None` se la riga non contiene riferimenti.
+pub fn expand(line: &str, events: &dyn Events) -> Result<Option<Expansion>, String> {
+    // `^old^new^` equivale a `!!:s^old^new^`
+    let line = match line.strip_prefix('^') {
+        Some(rest) => format!("!!:s^{}", rest),
+        None => line.to_string(),
+    };
+
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::with_capacity(line.len());
+    let mut print_only = false;
+    let mut changed = false;
+    let (mut single, mut double) = (false, false);
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if single {
+            if c == '\'' { single = false; }
+            out.push(c);
+            i += 1;
+            continue;
+        }
+        match c {
+            '\\' => {
+                out.push(c);
+                if let Some(&n) = chars.get(i + 1) { out.push(n); }
+                i += 2;
+                continue;
+            }
+            '\'' if !double => single = true,
+            '"' => double = !double,
+            '!' if is_reference(&chars, i) => {
+                let (text, next, p) = reference(&chars, i + 1, events)?;
+                out.push_str(&text);
+                print_only |= p;
+                changed = true;
+                i = next;
+                continue;
+            }
+            _ => {}
+        }
+        out.push(c);
+        i += 1;
+    }
+
+    Ok(changed.then_some(Expansion { line: out, print_only }))
+}
+
+// `!` seguito da spazio, `=`, `(` (extglob) o dentro `[!...]` / `$!` resta letterale
+fn is_reference(chars: &[char], i: usize) -> bool {
+    let Some(&next) = chars.get(i + 1) else { return false; };
+    if matches!(next, ' ' | '\t' | '\n' | '=' | '(' | '"') { return false; }
+    !(i > 0 && matches!(chars[i - 1], '[' | '$'))
+}
+
+fn not_found(what: &str) -> String {
+    format!("grsh: !{}: evento non trovato", what)
+}
+
+/// Analizza evento, designatore di parola e modificatori a partire da `i`
+/// (subito dopo il `!`). Restituisce (testo, posizione successiva, solo stampa).
+fn reference(chars: &[char], mut i: usize, events: &dyn Events) -> Result<(String, usize, bool), String> {
+    let last = || events.nth_back(1).ok_or_else(|| not_found("!"));
+
+    // --- Evento ---
+    let event = match chars[i] {
+        '!' => { i += 1; last()? }
+        // `!$`, `!^`, `!*`, `!:2` si riferiscono al comando precedente
+        '$' | '^' | '*' | ':' => last()?,
+        '-' | '0'..='9' => {
+            let start = i;
+            if chars[i] == '-' { i += 1; }
+            while i < chars.len() && chars[i].is_ascii_digit() { i += 1; }
+            let spec: String = chars[start..i].iter().collect();
+            let n: i64 = spec.parse().map_err(|_| not_found(&spec))?;
+            let event = if n < 0 { events.nth_back(n.unsigned_abs() as usize) } else { events.nth(n as usize) };
+            event.ok_or_else(|| not_found(&spec))?
+        }
+        '?' => {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && chars[i] != '?' && chars[i] != '\n' { i += 1; }
+            let needle: String = chars[start..i].iter().collect();
+            if i < chars.len() && chars[i] == '?' { i += 1; }
+            events.containing(&needle)
+                .ok_or_else(|| not_found(&format!("?{}", needle)))?
+        }
+        _ => {
+            let start = i;
+            while i < chars.len() && !ends_event(chars[i]) { i += 1; }
+            let prefix: String = chars[start..i].iter().collect();
+            events.starting_with(&prefix)
+                .ok_or_else(|| not_found(&prefix))?
+        }
+    };
+
+    // --- Designatore di parola ---
+    let words: Vec<String> = lexer::split_unquoted(&event, ' ')
+        .into_iter()
+        .filter(|w| !w.is_empty())
+        .collect();
+    let mut text = event.clone();
+    let designator_start = match chars.get(i) {
+        Some('^' | '$' | '*') => Some(i),
+        Some(':') if chars.get(i + 1).is_some_and(|&c| c.is_ascii_digit() || matches!(c, '^' | '$' | '*' | '-')) => Some(i + 1),
+        _ => None,
+    };
+    if let Some(start) = designator_start {
+        let (range, next) = designator(chars, start, words.len())?;
+        i = next;
+        text = match range {
+            Some((a, b)) => words[a..=b].join(" "),
+            None => String::new(),
+        };
+    }
+
+    // --- Modificatori ---
+    let mut print_only = false;
+    while chars.get(i) == Some(&':') {
+        let global = chars.get(i + 1) == Some(&'g');
+        let m = if global { i + 2 } else { i + 1 };
+        match chars.get(m) {
+            Some('h') => { text = head(&text); i = m + 1; }
+            Some('t') => { text = tail(&text); i = m + 1; }
+            Some('r') => { text = root(&text); i = m + 1; }
+            Some('e') => { text = extension(&text); i = m + 1; }
+            Some('p') => { print_only = true; i = m + 1; }
+            Some('s') if m + 1 < chars.len() => {
+                let (old, new, next) = substitution(chars, m + 1);
+                if old.is_empty() || !text.contains(&old) {
+                    return Err(format!("grsh: :s{}{}: sostituzione fallita", chars[m + 1], old));
+                }
+                let new = new.replace('&', &old);
+                text = if global { text.replace(&old, &new) } else { text.replacen(&old, &new, 1) };
+                i = next;
+            }
+            _ => {
+                let bad: String = chars.get(m).map(|c| c.to_string()).unwrap_or_default();
+                return Err(format!("grsh: :{}: modificatore errato", bad));
+            }
+        }
+    }
+
+    Ok((text, i, print_only))
+}
+
+fn ends_event(c: char) -> bool {
+    c.is_whitespace() || matches!(c, ':' | ';' | '|' | '&' | '<' | '>' | '(' | ')' | '\'' | '"')
+}
+
+/// Legge `^`, `$`, `*`, `n`, `x-y`, `x-`, `-y`, `x*`; restituisce l'intervallo
+/// di parole (`None` = nessuna parola, es. `!*` su un comando senza argomenti)
+fn designator(chars: &[char], mut i: usize, count: usize) -> Result<(Option<(usize, usize)>, usize), String> {
+    let last = count as i64 - 1;
+    let number = |i: &mut usize| -> Option<i64> {
+        match chars.get(*i) {
+            Some('^') => { *i += 1; Some(1) }
+            Some('$') => { *i += 1; Some(last) }
+            _ => {
+                let start = *i;
+                while *i < chars.len() && chars[*i].is_ascii_digit() { *i += 1; }
+                chars[start..*i].iter().collect::<String>().parse().ok()
+            }
+        }
+    };
+
+    let start_pos = i;
+    let (a, b) = if chars.get(i) == Some(&'*') {
+        i += 1;
+        if last < 1 { return Ok((None, i)); }
+        (1, last)
+    } else {
+        let a = if chars.get(i) == Some(&'-') { 0 } else {
+            number(&mut i).ok_or_else(|| bad_designator(chars, start_pos, i))?
+        };
+        match chars.get(i) {
+            Some('*') => {
+                i += 1;
+                if a > last { return Ok((None, i)); }
+                (a, last)
+            }
+            Some('-') => {
+                i += 1;
+                // `x-` esclude l'ultima parola, come in bash
+                let b = number(&mut i).unwrap_or(last - 1);
+                (a, b)
+            }
+            _ => (a, a),
+        }
+    };
+
+    if a < 0 || b > last || a > b {
+        return Err(bad_designator(chars, start_pos, i));
+    }
+    Ok((Some((a as usize, b as usize)), i))
+}
+
+fn bad_designator(chars: &[char], start: usize, end: usize) -> String {
+    let spec: String = chars[start..end.max(start + 1).min(chars.len())].iter().collect();
+    format!("grsh: :{}: designatore di parola errato", spec)
+}
+
+// `s/old/new/` con delimitatore qualsiasi; il delimitatore finale è facoltativo
+fn substitution(chars: &[char], mut i: usize) -> (String, String, usize) {
+    let delim = chars[i];
+    i += 1;
+    let mut parts = [String::new(), String::new()];
+    for part in parts.iter_mut() {
+        while i < chars.len() && chars[i] != delim {
+            if chars[i] == '\\' && chars.get(i + 1) == Some(&delim) { i += 1; }
+            part.push(chars[i]);
+            i += 1;
+        }
+        if i < chars.len() { i += 1; }
+    }
+    let [old, new] = parts;
+    (old, new, i)
+}
+
+// `:h` toglie l'ultimo componente del percorso
+fn head(s: &str) -> String {
+    match s.rfind('/') {
+        Some(0) => "/".to_string(),
+        Some(pos) => s[..pos].to_string(),
+        None => s.to_string(),
+    }
+}
+
+// `:t` tiene solo l'ultimo componente
+fn tail(s: &str) -> String {
+    s.rsplit('/').next().unwrap_or(s).to_string()
+}
+
+// `:r` toglie l'estensione
+fn root(s: &str) -> String {
+    let base = s.rfind('/').map(|p| p + 1).unwrap_or(0);
+    match s[base..].rfind('.') {
+        Some(dot) if dot > 0 => s[..base + dot].to_string(),
+        _ => s.to_string(),
+    }
+}
+
+// `:e` tiene solo l'estensione (senza punto, come tcsh)
+fn extension(s: &str) -> String {
+    let base = s.rfind('/').map(|p| p + 1).unwrap_or(0);
+    match s[base..].rfind('.') {
+        Some(dot) if dot > 0 => s[base + dot + 1..].to_string(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> Vec<String> {
+        ["ls -l /tmp/a.txt", "echo one two three", "git status"].iter().map(|e| e.to_string()).collect()
+    }
+
+    fn expanded(line: &str) -> String {
+        expand(line, &events()).unwrap().expect("nessun riferimento espanso").line
+    }
+
+    fn error(line: &str) -> String {
+        expand(line, &events()).unwrap_err()
+    }
+
+    #[test]
+    fn events_by_position() {
+        assert_eq!(expanded("!!"), "git status");
+        assert_eq!(expanded("sudo !!"), "sudo git status");
+        assert_eq!(expanded("!1"), "ls -l /tmp/a.txt");
+        assert_eq!(expanded("!-2"), "echo one two three");
+    }
+
+    #[test]
+    fn events_by_text() {
+        assert_eq!(expanded("!ec"), "echo one two three");
+        assert_eq!(expanded("!?stat?"), "git status");
+        assert_eq!(expanded("!?two? four"), "echo one two three four");
+        assert_eq!(expanded("^status^log"), "git log");
+    }
+
+    #[test]
+    fn word_designators() {
+        assert_eq!(expanded("echo !$"), "echo status");
+        assert_eq!(expanded("!2:1"), "one");
+        assert_eq!(expanded("!2:^"), "one");
+        assert_eq!(expanded("!2:1-2"), "one two");
+        assert_eq!(expanded("!2:1-"), "one two");
+        assert_eq!(expanded("!2*"), "one two three");
+        assert_eq!(expanded("!2:0"), "echo");
+    }
+
+    #[test]
+    fn modifiers() {
+        assert_eq!(expanded("!1:$:h"), "/tmp");
+        assert_eq!(expanded("!1:$:t"), "a.txt");
+        assert_eq!(expanded("!1:$:r"), "/tmp/a");
+        assert_eq!(expanded("!1:$:e"), "txt");
+        assert_eq!(expanded("!!:gs/t/T/"), "giT sTaTus");
+        let print = expand("!!:p", &events()).unwrap().unwrap();
+        assert!(print.print_only);
+        assert_eq!(print.line, "git status");
+    }
+
+    #[test]
+    fn literal_bangs() {
+        for line in ["echo a != b", "echo '!!'", "echo \\!!", "echo $!", "ls [!a]*", "ls !(x)"] {
+            assert!(expand(line, &events()).unwrap().is_none(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn error_paths() {
+        assert_eq!(error("!99"), "grsh: !99: evento non trovato");
+        assert_eq!(error("!-9"), "grsh: !-9: evento non trovato");
+        assert_eq!(error("!xyz"), "grsh: !xyz: evento non trovato");
+        assert_eq!(error("!?nope?"), "grsh: !?nope: evento non trovato");
+        assert_eq!(error("!2:7"), "grsh: :7: designatore di parola errato");
+        assert_eq!(error("!!:s/zz/y/"), "grsh: :s/zz: sostituzione fallita");
+        assert_eq!(error("!!:q"), "grsh: :q: modificatore errato");
+        assert_eq!(expand("!!", &Vec::new()).unwrap_err(), "grsh: !!: evento non trovato");
+    }
+}
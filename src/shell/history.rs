@@ -0,0 +1,622 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use nix::unistd;
+use reedline::{
+    CommandLineSearch, FileBackedHistory, History, HistoryItem, HistoryItemId, HistorySessionId, Reedline,
+    SearchDirection, SearchFilter, SearchQuery, SqliteBackedHistory,
+};
+use crate::shell::histexpand;
+use crate::shell::histfilter::{self, DupMode};
+use crate::shell::{config, osstr, state};
+
+// --- CRONOLOGIA CONDIVISA ---
+// Reedline possiede la cronologia che gli viene passata: per poterla leggere anche
+// dall'espansione `!!` e dai builtin, l'istanza vera vive qui e all'editor diamo
+// `SharedHistory`, che inoltra ogni chiamata.
+//
+// Le voci stanno in un database SQLite (~/.grsh_history.db, o `history.path`
+// di config.toml) con orario, directory,
+// host, sessione, exit status e durata di ogni comando. Ogni comando viene scritto
+// subito, quindi più shell aperte condividono lo stesso file senza sovrascriversi.
+//
+// Le letture non scorrono mai tutta la tabella: contano le voci, le leggono a
+// pagine dalla più recente o prendono l'intervallo di numeri richiesto.
+
+lazy_static! {
+    static ref HISTORY: Mutex<Box<dyn History>> = Mutex::new(open());
+
+    // Identificativo della sessione corrente e suo inizio
+    // (inizializzati da `session()` quando parte il REPL)
+    static ref SESSION_START: DateTime<Utc> = Utc::now();
+    static ref SESSION: Option<HistorySessionId> = {
+        lazy_static::initialize(&SESSION_START);
+        Reedline::create_history_session_id()
+    };
+
+    static ref HOSTNAME: Option<String> = unistd::gethostname()
+        .ok()
+        .map(|h| h.to_string_lossy().into_owned());
+}
+
+fn home_file(name: &str) -> PathBuf {
+    let mut path = dirs::home_dir().unwrap_or(PathBuf::from("."));
+    path.push(name);
+    path
+}
+
+/// Riapre il database dopo un `reload` (`history.path` può essere cambiato)
+pub fn reopen() {
+    let _ = HISTORY.lock().unwrap().sync();
+    let history = open();
+    *HISTORY.lock().unwrap() = history;
+}
+
+fn open() -> Box<dyn History> {
+    let db_path = config::get().history.path.clone();
+    let fresh = !db_path.exists();
+    let history: Box<dyn History> = match SqliteBackedHistory::with_file(db_path.clone(), *SESSION, Some(*SESSION_START)) {
+        Ok(mut history) => {
+            if fresh { import_legacy(&db_path); }
+            prune(&mut history);
+            Box::new(history)
+        }
+        Err(e) => {
+            eprintln!("grsh: database della cronologia non disponibile ({}), uso ~/.grsh_history", e);
+            match FileBackedHistory::with_file(limit(), home_file(".grsh_history")) {
+                Ok(history) => Box::new(history),
+                Err(e) => {
+                    eprintln!("grsh: cronologia non disponibile: {}", e);
+                    Box::new(FileBackedHistory::new(limit()).expect("History error"))
+                }
+            }
+        }
+    };
+    PULLED.store(newest_id(history.as_ref()), Ordering::Relaxed);
+    history
+}
+
+// Al primo avvio importa il vecchio ~/.grsh_history (una voce per riga,
+// con gli a capo codificati come `<\n>`); il file resta al suo posto.
+// Solo le ultime voci entro il limite: le altre verrebbero subito potate.
+fn import_legacy(db_path: &Path) {
+    let Ok(content) = fs::read_to_string(home_file(".grsh_history")) else { return; };
+    let mut lines: Vec<String> = content.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.replace("<\\n>", "\n"))
+        .collect();
+    lines.drain(..lines.len().saturating_sub(limit()));
+    if lines.is_empty() { return; }
+    match insert_legacy(db_path, &lines) {
+        Ok(()) => eprintln!("grsh: importate {} voci da ~/.grsh_history", lines.len()),
+        Err(e) => eprintln!("grsh: importazione di ~/.grsh_history non riuscita: {}", e),
+    }
+}
+
+// Una sola transazione: con un'istruzione alla volta SQLite scriverebbe su disco a ogni riga
+fn insert_legacy(db_path: &Path, lines: &[String]) -> rusqlite::Result<()> {
+    let mut db = rusqlite::Connection::open(db_path)?;
+    let tx = db.transaction()?;
+    {
+        let mut insert = tx.prepare("insert into history (command_line) values (?1)")?;
+        for line in lines { insert.execute([line])?; }
+    }
+    tx.commit()
+}
+
+/// Limite di voci da `set history = N` (come tcsh), altrimenti `history.size` di config.toml
+fn limit() -> usize {
+    state::get_var("history")
+        .and_then(|v| v.split_whitespace().next()?.parse().ok())
+        .unwrap_or_else(|| config::get().history.size)
+}
+
+// Salvataggi tra una potatura e l'altra
+const PRUNE_EVERY: usize = 100;
+static SAVES: AtomicUsize = AtomicUsize::new(0);
+
+// Elimina le voci più vecchie oltre il limite
+fn prune(history: &mut dyn History) {
+    let Ok(count) = history.count_all() else { return; };
+    let excess = count - limit() as i64;
+    if excess <= 0 { return; }
+    let oldest = query(SearchDirection::Forward, None, SearchFilter::anything(None), Some(excess));
+    for item in history.search(oldest).unwrap_or_default() {
+        if let Some(id) = item.id { let _ = history.delete(id); }
+    }
+}
+
+/// Sessione da passare all'editor: le frecce scorrono prima i comandi di questa shell
+pub fn session() -> Option<HistorySessionId> {
+    // Aperta subito: le voci delle altre shell visibili sono quelle presenti ora
+    lazy_static::initialize(&HISTORY);
+    *SESSION
+}
+
+// Query senza filtri di tempo (non funzionano con SQLite in reedline 0.34) e con il
+// solo `start_id`, escluso in entrambe le direzioni e in entrambi i backend
+fn query(direction: SearchDirection, start_id: Option<i64>, filter: SearchFilter, limit: Option<i64>) -> SearchQuery {
+    SearchQuery {
+        direction,
+        start_time: None, end_time: None,
+        start_id: start_id.map(HistoryItemId::new), end_id: None,
+        filter,
+        limit,
+    }
+}
+
+// --- VOCI VISIBILI ---
+// Da questa sessione si vedono tutte le voci fino a `PULLED` (la più recente
+// all'apertura o all'ultimo `history -r`) e, dopo, solo le proprie.
+
+// Id della voce più recente, -1 se la cronologia è vuota
+static PULLED: AtomicI64 = AtomicI64::new(-1);
+
+// Voci lette alla volta quando si scorre dalla più recente
+const PAGE: i64 = 64;
+
+fn newest_id(history: &dyn History) -> i64 {
+    history.search(query(SearchDirection::Backward, None, SearchFilter::anything(None), Some(1)))
+        .ok()
+        .and_then(|items| items.first()?.id)
+        .map_or(-1, |id| id.0)
+}
+
+/// Testo cercato nei comandi
+pub enum Text {
+    Prefix(String),
+    Contains(String),
+    Exact(String),
+}
+
+/// Ricerca nelle voci visibili
+#[derive(Default)]
+pub struct Search {
+    pub text: Option<Text>,
+    pub cwd: Option<String>,
+    // Scorrendo dalla più recente si ferma alla prima voce precedente
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl Search {
+    // `own`: solo le voci di questa sessione (SQLite aggiunge quelle precedenti al
+    // suo inizio, la cronologia su file ignora il filtro: lì sono visibili tutte)
+    fn filter(&self, own: bool) -> SearchFilter {
+        let mut filter = SearchFilter::anything(if own { *SESSION } else { None });
+        filter.command_line = self.text.as_ref().map(|text| match text {
+            Text::Prefix(t) => CommandLineSearch::Prefix(t.clone()),
+            Text::Contains(t) => CommandLineSearch::Substring(t.clone()),
+            Text::Exact(t) => CommandLineSearch::Exact(t.clone()),
+        });
+        filter.cwd_exact = self.cwd.clone();
+        filter
+    }
+
+    fn is_filtered(&self) -> bool {
+        self.text.is_some() || self.cwd.is_some()
+    }
+}
+
+// Voci visibili che soddisfano `search`
+fn count_visible(history: &dyn History, search: &Search) -> usize {
+    let pulled = PULLED.load(Ordering::Relaxed);
+    let older = history.count(query(SearchDirection::Backward, Some(pulled + 1), search.filter(false), None));
+    let newer = history.count(query(SearchDirection::Forward, Some(pulled), search.filter(true), None));
+    (older.unwrap_or(0) + newer.unwrap_or(0)) as usize
+}
+
+// Numero di una voce visibile: quelle dopo `PULLED` sono solo di questa sessione
+fn number(history: &dyn History, id: i64) -> usize {
+    let pulled = PULLED.load(Ordering::Relaxed);
+    let all = Search::default();
+    if id <= pulled {
+        let upto = history.count(query(SearchDirection::Backward, Some(id + 1), all.filter(false), None));
+        return upto.unwrap_or(0) as usize;
+    }
+    let after = history.count(query(SearchDirection::Forward, Some(id), all.filter(true), None));
+    count_visible(history, &all).saturating_sub(after.unwrap_or(0) as usize)
+}
+
+// Voci visibili con i numeri da `first` a `last`, dalla più vecchia. Fino a `PULLED`
+// si legge dal lato più vicino, inizio o fine di quella parte.
+fn numbered(history: &dyn History, first: usize, last: usize) -> Vec<(usize, HistoryItem)> {
+    let first = first.max(1);
+    if first > last { return Vec::new(); }
+    let pulled = PULLED.load(Ordering::Relaxed);
+    let all = Search::default();
+    let older = history.count(query(SearchDirection::Backward, Some(pulled + 1), all.filter(false), None)).unwrap_or(0) as usize;
+
+    let mut items = Vec::new();
+    if first <= older {
+        let end = last.min(older);
+        if end <= older - first {
+            let page = history.search(query(SearchDirection::Forward, None, all.filter(false), Some(end as i64)));
+            items.extend(page.unwrap_or_default().into_iter().skip(first - 1));
+        } else {
+            let page = history.search(query(SearchDirection::Backward, Some(pulled + 1), all.filter(false), Some((older - first + 1) as i64)));
+            items.extend(page.unwrap_or_default().into_iter().rev().take(end - first + 1));
+        }
+    }
+    if last > older {
+        let skip = first.max(older + 1) - older - 1;
+        let page = history.search(query(SearchDirection::Forward, Some(pulled), all.filter(true), Some((last - older) as i64)));
+        items.extend(page.unwrap_or_default().into_iter().skip(skip));
+    }
+    items.into_iter().enumerate().map(|(i, item)| (first + i, item)).collect()
+}
+
+// Voci visibili che soddisfano `search`, dalla più recente, lette a pagine
+struct Newest<'a> {
+    history: &'a dyn History,
+    search: Search,
+    // Ancora tra le voci di questa sessione dopo `PULLED`
+    own: bool,
+    // Id sotto cui continuare
+    cursor: Option<i64>,
+    page: std::vec::IntoIter<HistoryItem>,
+}
+
+fn newest(history: &dyn History, search: Search) -> Newest<'_> {
+    Newest { history, search, own: true, cursor: None, page: Vec::new().into_iter() }
+}
+
+impl Newest<'_> {
+    // Passa alle voci fino a `PULLED`, di tutte le sessioni
+    fn leave_own(&mut self, pulled: i64) {
+        self.own = false;
+        self.cursor = Some(pulled + 1);
+        self.page = Vec::new().into_iter();
+    }
+}
+
+impl Iterator for Newest<'_> {
+    type Item = HistoryItem;
+
+    fn next(&mut self) -> Option<HistoryItem> {
+        let pulled = PULLED.load(Ordering::Relaxed);
+        loop {
+            if let Some(item) = self.page.next() {
+                let id = item.id.map_or(-1, |id| id.0);
+                if self.own && id <= pulled {
+                    self.leave_own(pulled);
+                    continue;
+                }
+                self.cursor = Some(id);
+                return Some(item);
+            }
+            let filter = self.search.filter(self.own);
+            let page = self.history
+                .search(query(SearchDirection::Backward, self.cursor, filter, Some(PAGE)))
+                .unwrap_or_default();
+            if page.is_empty() {
+                if !self.own { return None; }
+                self.leave_own(pulled);
+                continue;
+            }
+            self.page = page.into_iter();
+        }
+    }
+}
+
+// Voci identiche a `line`
+fn matching(history: &dyn History, line: &str) -> Vec<HistoryItemId> {
+    let search = Search { text: Some(Text::Exact(line.to_string())), ..Default::default() };
+    history.search(query(SearchDirection::Forward, None, search.filter(false), None))
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|h| h.id)
+        .collect()
+}
+
+/// Numero di voci visibili, cioè il numero dell'ultima (`!n`, `history -d`, `%h`)
+pub fn count() -> usize {
+    count_visible(HISTORY.lock().unwrap().as_ref(), &Search::default())
+}
+
+/// Voci con i numeri da `first` a `last` (da 1), dalla più vecchia
+pub fn range(first: usize, last: usize) -> Vec<(usize, HistoryItem)> {
+    numbered(HISTORY.lock().unwrap().as_ref(), first, last)
+}
+
+/// Le ultime `limit` voci che soddisfano `search`, dalla più vecchia, con il loro numero
+pub fn latest(search: Search, limit: Option<usize>) -> Vec<(usize, HistoryItem)> {
+    let history = HISTORY.lock().unwrap();
+    let filtered = search.is_filtered();
+    let since = search.since;
+
+    let mut items: Vec<HistoryItem> = newest(history.as_ref(), search)
+        .take_while(|h| since.is_none_or(|s| h.start_timestamp.is_some_and(|t| t >= s)))
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+    items.reverse();
+
+    // Senza filtri sul testo i numeri sono consecutivi fino all'ultima voce
+    if filtered {
+        return items.into_iter()
+            .map(|h| (number(history.as_ref(), h.id.map_or(-1, |id| id.0)), h))
+            .collect();
+    }
+    let first = count_visible(history.as_ref(), &Search::default()) + 1 - items.len();
+    items.into_iter().enumerate().map(|(i, h)| (first + i, h)).collect()
+}
+
+/// Vero se la cronologia registra directory e orari (no con il vecchio file di testo)
+pub fn has_metadata() -> bool {
+    let history = HISTORY.lock().unwrap();
+    let last = newest(history.as_ref(), Search::default()).next();
+    last.is_some_and(|h| h.cwd.is_some() || h.start_timestamp.is_some())
+}
+
+/// Svuota la cronologia (anche su disco)
+pub fn clear() -> reedline::Result<()> {
+    let mut history = HISTORY.lock().unwrap();
+    history.clear()?;
+    history.sync()?;
+    Ok(())
+}
+
+/// Rimuove la voce numero `number`
+pub fn delete(number: usize) -> Result<(), String> {
+    let mut history = HISTORY.lock().unwrap();
+    let Some((_, item)) = numbered(history.as_ref(), number, number).pop() else {
+        return Err(format!("{}: voce inesistente", number));
+    };
+
+    let deleted = item.id.is_some_and(|id| history.delete(id).is_ok());
+    if !deleted {
+        // La cronologia su file non supporta la cancellazione: la riscriviamo senza la voce
+        let items = history
+            .search(SearchQuery::everything(SearchDirection::Forward, None))
+            .map_err(|e| e.to_string())?;
+        history.clear().map_err(|e| e.to_string())?;
+        for other in &items {
+            if other.id == item.id { continue; }
+            let mut copy = other.clone();
+            copy.id = None;
+            history.save(copy).map_err(|e| e.to_string())?;
+        }
+    }
+    history.sync().map_err(|e| e.to_string())
+}
+
+/// Scrive su disco le voci non ancora salvate (`history -w`)
+pub fn write() -> std::io::Result<()> {
+    HISTORY.lock().unwrap().sync()
+}
+
+/// Rende visibili i comandi registrati finora dalle altre shell (`history -r`,
+/// o a ogni prompt con `set sharehistory`); le frecce restano sulla sessione
+pub fn pull() {
+    let mut history = HISTORY.lock().unwrap();
+    // La cronologia su file legge qui le righe aggiunte dalle altre shell
+    let _ = history.sync();
+    PULLED.store(newest_id(history.as_ref()), Ordering::Relaxed);
+}
+
+/// Mette in cronologia, al posto dell'invocazione di `fc`, i comandi che ha rieseguito
+pub fn replace_fc(line: &str) {
+    replace_last(|saved| saved.split_whitespace().next() == Some("fc"), line);
+}
+
+/// Mette in cronologia la riga corretta da `correct` al posto di quella digitata
+pub fn replace_corrected(typed: &str, line: &str) {
+    // La riga digitata è stata salvata già filtrata (o non è stata salvata affatto)
+    let Some(saved) = histfilter::filter(typed) else { return; };
+    replace_last(|last| last == saved, line);
+}
+
+// Sostituisce l'ultima voce di questa sessione, se è quella attesa, passando la
+// nuova riga dagli stessi filtri del salvataggio: se la rifiutano la voce sparisce
+fn replace_last(is_target: impl Fn(&str) -> bool, line: &str) {
+    let mut history = HISTORY.lock().unwrap();
+    let Some(item) = newest(history.as_ref(), Search::default()).next() else { return; };
+    let is_target = item.session_id == *SESSION && is_target(&item.command_line);
+    let Some(id) = item.id.filter(|_| is_target) else { return; };
+    match histfilter::filter(line) {
+        Some(line) => {
+            let _ = history.update(id, &move |mut h| { h.command_line = line.clone(); h });
+        }
+        None => { let _ = history.delete(id); }
+    }
+    let _ = history.sync();
+}
+
+/// Durata in secondi da `30s`, `10m`, `1h`, `2d`, `1w` (senza unità: secondi)
+pub fn parse_duration(spec: &str) -> Option<i64> {
+    let split = spec.find(|c: char| !c.is_ascii_digit()).unwrap_or(spec.len());
+    let (num, unit) = spec.split_at(split);
+    let n: i64 = num.parse().ok()?;
+    let factor = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return None,
+    };
+    Some(n * factor)
+}
+
+/// Eventi di `!n`, `!str`, `!!` letti dalla cronologia visibile
+pub struct Events;
+
+impl Events {
+    fn last_matching(&self, text: Text) -> Option<String> {
+        latest(Search { text: Some(text), ..Default::default() }, Some(1)).pop().map(|(_, h)| h.command_line)
+    }
+}
+
+impl histexpand::Events for Events {
+    fn nth(&self, n: usize) -> Option<String> {
+        range(n, n).pop().map(|(_, h)| h.command_line)
+    }
+
+    fn nth_back(&self, n: usize) -> Option<String> {
+        let history = HISTORY.lock().unwrap();
+        newest(history.as_ref(), Search::default()).nth(n.checked_sub(1)?).map(|h| h.command_line)
+    }
+
+    fn starting_with(&self, prefix: &str) -> Option<String> {
+        self.last_matching(Text::Prefix(prefix.to_string()))
+    }
+
+    fn containing(&self, text: &str) -> Option<String> {
+        self.last_matching(Text::Contains(text.to_string()))
+    }
+}
+
+/// Salva una riga già espansa dal REPL; restituisce la voce creata, se i filtri
+/// l'hanno accettata
+pub fn add(line: &str) -> Option<HistoryItemId> {
+    store(HistoryItem::from_command_line(line)).id
+}
+
+/// Aggiorna una voce salvata con `add` (exit status e durata)
+pub fn update(id: HistoryItemId, updater: &dyn Fn(HistoryItem) -> HistoryItem) {
+    let _ = HISTORY.lock().unwrap().update(id, updater);
+}
+
+// Filtri, duplicati e scrittura su disco di una voce nuova
+fn store(mut h: HistoryItem) -> HistoryItem {
+    let mut history = HISTORY.lock().unwrap();
+
+    // Spazio iniziale, `histignore` e segreti: decisi prima di toccare il disco
+    match histfilter::filter(&h.command_line) {
+        Some(line) => h.command_line = line,
+        None => return h,
+    }
+    match histfilter::dup_mode() {
+        DupMode::Prev if newest(history.as_ref(), Search::default()).next()
+            .is_some_and(|last| last.command_line == h.command_line) => return h,
+        DupMode::All if !matching(history.as_ref(), &h.command_line).is_empty() => return h,
+        DupMode::Erase => {
+            for id in matching(history.as_ref(), &h.command_line) {
+                let _ = history.delete(id);
+            }
+        }
+        _ => {}
+    }
+
+    h.start_timestamp.get_or_insert_with(Utc::now);
+    if h.cwd.is_none() {
+        h.cwd = env::current_dir().ok().map(|d| osstr::encode(d.as_os_str()));
+    }
+    if h.hostname.is_none() { h.hostname = HOSTNAME.clone(); }
+    if h.session_id.is_none() { h.session_id = *SESSION; }
+
+    // Più shell scrivono sullo stesso database: se è bloccato riproviamo per un po',
+    // senza tenere occupata la cronologia (prompt, `!!` e `history`) durante l'attesa
+    drop(history);
+    let mut attempts = 0;
+    let item = loop {
+        let result = HISTORY.lock().unwrap().save(h.clone());
+        match result {
+            Ok(item) => break item,
+            Err(_) if attempts < 20 => {
+                attempts += 1;
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(e) => {
+                eprintln!("grsh: cronologia: {}", e);
+                return h;
+            }
+        }
+    };
+    let mut history = HISTORY.lock().unwrap();
+    let _ = history.sync();
+    // Le voci in eccesso si tolgono all'apertura e poi ogni `PRUNE_EVERY` salvataggi
+    if SAVES.fetch_add(1, Ordering::Relaxed) % PRUNE_EVERY == PRUNE_EVERY - 1 {
+        prune(history.as_mut());
+    }
+    item
+}
+
+/// Cronologia da passare a reedline
+pub struct SharedHistory;
+
+impl History for SharedHistory {
+    // Le righe con riferimenti `!` non passano da qui: il REPL le espande e le salva
+    // con `add`, altrimenti `!!` troverebbe in cronologia la riga stessa
+    fn save(&mut self, h: HistoryItem) -> reedline::Result<HistoryItem> {
+        if histexpand::has_references(&h.command_line) { return Ok(h); }
+        Ok(store(h))
+    }
+
+    fn load(&self, id: HistoryItemId) -> reedline::Result<HistoryItem> {
+        HISTORY.lock().unwrap().load(id)
+    }
+
+    fn count(&self, query: SearchQuery) -> reedline::Result<i64> {
+        HISTORY.lock().unwrap().count(query)
+    }
+
+    fn search(&self, query: SearchQuery) -> reedline::Result<Vec<HistoryItem>> {
+        HISTORY.lock().unwrap().search(query)
+    }
+
+    fn update(
+        &mut self,
+        id: HistoryItemId,
+        updater: &dyn Fn(HistoryItem) -> HistoryItem,
+    ) -> reedline::Result<()> {
+        HISTORY.lock().unwrap().update(id, updater)
+    }
+
+    fn clear(&mut self) -> reedline::Result<()> {
+        HISTORY.lock().unwrap().clear()
+    }
+
+    fn delete(&mut self, h: HistoryItemId) -> reedline::Result<()> {
+        HISTORY.lock().unwrap().delete(h)
+    }
+
+    fn sync(&mut self) -> std::io::Result<()> {
+        HISTORY.lock().unwrap().sync()
+    }
+
+    fn session(&self) -> Option<HistorySessionId> {
+        HISTORY.lock().unwrap().session()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_lookups() {
+        // In memoria SQLite non filtra per sessione: dopo `PULLED` sono visibili tutte
+        let mut history = SqliteBackedHistory::in_memory().unwrap();
+        for i in 1..=150 {
+            history.save(HistoryItem::from_command_line(format!("cmd {}", i))).unwrap();
+        }
+        PULLED.store(100, Ordering::Relaxed);
+        let all = Search::default();
+        assert_eq!(count_visible(&history, &all), 150);
+
+        // Da entrambi i lati della parte fino a `PULLED` e oltre
+        let lines = |first, last| -> Vec<String> {
+            numbered(&history, first, last).into_iter().map(|(n, h)| format!("{} {}", n, h.command_line)).collect()
+        };
+        assert_eq!(lines(2, 3), ["2 cmd 2", "3 cmd 3"]);
+        assert_eq!(lines(97, 102), ["97 cmd 97", "98 cmd 98", "99 cmd 99", "100 cmd 100", "101 cmd 101", "102 cmd 102"]);
+        assert_eq!(lines(150, 151), ["150 cmd 150"]);
+        assert!(lines(0, 0).is_empty());
+
+        // A pagine, oltre `PAGE` voci
+        let recent: Vec<String> = newest(&history, Search::default()).skip(69).take(2).map(|h| h.command_line).collect();
+        assert_eq!(recent, ["cmd 81", "cmd 80"]);
+        assert_eq!(newest(&history, Search::default()).count(), 150);
+
+        let found = newest(&history, Search { text: Some(Text::Prefix("cmd 12".to_string())), ..Default::default() }).next().unwrap();
+        assert_eq!(found.command_line, "cmd 129");
+        assert_eq!(number(&history, found.id.unwrap().0), 129);
+        assert_eq!(number(&history, 42), 42);
+    }
+}
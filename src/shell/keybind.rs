@@ -0,0 +1,500 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use crossterm::event::{Event, KeyEvent};
+use lazy_static::lazy_static;
+use reedline::{
+    EditCommand, EditMode, KeyCode, KeyModifiers, PromptEditMode, PromptViMode, ReedlineEvent,
+    ReedlineRawEvent,
+};
+use crate::shell::state;
+
+// --- TASTI PERSONALIZZATI: IL BUILTIN `bindkey` ---
+//   bindkey                    elenca i tasti definiti da grsh e dall'utente
+//   bindkey -l                 elenca le azioni disponibili
+//   bindkey ^X^R clear-screen  lega una sequenza (anche di più tasti) a un'azione
+//   bindkey -c ^Xg git status  lega una sequenza a un comando della shell
+//   bindkey -r ^G              toglie il tasto (anche se era di reedline)
+//   bindkey -a ...             agisce sulla modalità normal di vi
+//   bindkey -k up ...          tasti con nome: up, down, left, right, home, end...
+// Le sequenze si scrivono come in tcsh: `^X` (Ctrl), `^[` o `\e` (Esc/Alt), `M-x`, `C-x`.
+
+/// Un tasto premuto, con i modificatori
+pub type Key = (KeyModifiers, KeyCode);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Widget(String),
+    Command(String),
+    Unbound,
+}
+
+#[derive(Debug, Clone)]
+struct Binding {
+    keys: Vec<Key>,
+    // Vale nella modalità normal di vi (`-a`) invece che in emacs / vi insert
+    vi_command: bool,
+    action: Action,
+}
+
+type WidgetFn = fn() -> ReedlineEvent;
+
+fn edit(command: EditCommand) -> ReedlineEvent {
+    ReedlineEvent::Edit(vec![command])
+}
+
+// Azioni collegabili, con i nomi di tcsh dove esistono
+static WIDGETS: &[(&str, WidgetFn)] = &[
+    ("accept-line", || ReedlineEvent::Enter),
+    ("autosuggest-accept", || ReedlineEvent::HistoryHintComplete),
+    ("autosuggest-accept-word", || ReedlineEvent::HistoryHintWordComplete),
+    ("backward-char", || edit(EditCommand::MoveLeft { select: false })),
+    ("backward-delete-char", || edit(EditCommand::Backspace)),
+    ("backward-delete-word", || edit(EditCommand::CutWordLeft)),
+    ("backward-kill-line", || edit(EditCommand::CutFromLineStart)),
+    ("backward-word", || edit(EditCommand::MoveWordLeft { select: false })),
+    ("beginning-of-line", || edit(EditCommand::MoveToLineStart { select: false })),
+    ("capitalize-char", || edit(EditCommand::CapitalizeChar)),
+    ("clear-line", || edit(EditCommand::Clear)),
+    ("clear-screen", || ReedlineEvent::ClearScreen),
+    ("complete-word", || ReedlineEvent::UntilFound(vec![
+        ReedlineEvent::Menu("completion_menu".to_string()),
+        ReedlineEvent::MenuNext,
+    ])),
+    ("delete-char", || edit(EditCommand::Delete)),
+    ("delete-word", || edit(EditCommand::CutWordRight)),
+    ("down-history", || ReedlineEvent::NextHistory),
+    ("downcase-word", || edit(EditCommand::LowercaseWord)),
+    ("edit-command-line", || ReedlineEvent::OpenEditor),
+    ("end-of-file", || ReedlineEvent::CtrlD),
+    ("end-of-line", || edit(EditCommand::MoveToLineEnd { select: false })),
+    ("forward-char", || edit(EditCommand::MoveRight { select: false })),
+    ("forward-word", || edit(EditCommand::MoveWordRight { select: false })),
+    ("i-search-back", || ReedlineEvent::SearchHistory),
+    ("insert-newline", || edit(EditCommand::InsertNewline)),
+    ("interrupt", || ReedlineEvent::CtrlC),
+    ("kill-line", || edit(EditCommand::CutToLineEnd)),
+    ("kill-whole-line", || edit(EditCommand::CutCurrentLine)),
+    ("redo", || edit(EditCommand::Redo)),
+    ("transpose-chars", || edit(EditCommand::SwapGraphemes)),
+    ("transpose-words", || edit(EditCommand::SwapWords)),
+    ("undefined-key", || ReedlineEvent::None),
+    ("undo", || edit(EditCommand::Undo)),
+    ("up-history", || ReedlineEvent::PreviousHistory),
+    ("upcase-word", || edit(EditCommand::UppercaseWord)),
+    ("yank", || edit(EditCommand::PasteCutBufferBefore)),
+];
+
+fn widget(name: &str) -> Option<ReedlineEvent> {
+    WIDGETS.iter().find(|(n, _)| *n == name).map(|(_, f)| f())
+}
+
+// Tasti propri di grsh, oltre a quelli di reedline
+fn defaults() -> Vec<Binding> {
+    let bind = |keys: &[Key], name: &str| Binding {
+        keys: keys.to_vec(),
+        vi_command: false,
+        action: Action::Widget(name.to_string()),
+    };
+    let ctrl = |c| (KeyModifiers::CONTROL, KeyCode::Char(c));
+    vec![
+        bind(&[ctrl('g')], "autosuggest-accept"),
+        bind(&[(KeyModifiers::NONE, KeyCode::Tab)], "complete-word"),
+        bind(&[ctrl('x'), ctrl('e')], "edit-command-line"),
+    ]
+}
+
+lazy_static! {
+    static ref BINDINGS: Mutex<Vec<Binding>> = Mutex::new(defaults());
+
+    // Comando legato a un tasto appena richiesto all'editor, letto dal REPL
+    static ref HOST_COMMAND: Mutex<Option<String>> = Mutex::new(None);
+}
+
+// Cresce a ogni modifica: il REPL ricostruisce l'editor quando cambia
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+pub fn generation() -> usize {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+/// Comando da eseguire perché è stato premuto un tasto legato con `bindkey -c`
+pub fn take_host_command() -> Option<String> {
+    HOST_COMMAND.lock().unwrap().take()
+}
+
+// --- SEQUENZE DI TASTI ---
+
+const NAMED_KEYS: &[(&str, KeyCode)] = &[
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("insert", KeyCode::Insert),
+    ("delete", KeyCode::Delete),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+];
+
+fn named_key(name: &str) -> Option<KeyCode> {
+    let lower = name.to_ascii_lowercase();
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        return (1..=12).contains(&n).then_some(KeyCode::F(n));
+    }
+    NAMED_KEYS.iter().find(|(n, _)| *n == lower).map(|(_, code)| *code)
+}
+
+// `^X` come lo legge crossterm dal terminale
+fn control(c: char) -> Option<Key> {
+    match c {
+        '?' => Some((KeyModifiers::NONE, KeyCode::Backspace)),
+        '[' => Some((KeyModifiers::NONE, KeyCode::Esc)),
+        'I' | 'i' => Some((KeyModifiers::NONE, KeyCode::Tab)),
+        'M' | 'm' => Some((KeyModifiers::NONE, KeyCode::Enter)),
+        c if c.is_ascii_alphabetic() || "\\]^_".contains(c) => {
+            Some((KeyModifiers::CONTROL, KeyCode::Char(c.to_ascii_lowercase())))
+        }
+        _ => None,
+    }
+}
+
+// `M-x` / `\M-x` (Alt) e `C-x` / `\C-x` (Ctrl): (modificatore, carattere, lunghezza)
+fn modified(chars: &[char]) -> Option<(char, char, usize)> {
+    let skip = usize::from(chars.first() == Some(&'\\'));
+    match *chars.get(skip..skip + 3)? {
+        [m @ ('M' | 'C'), '-', c] => Some((m, c, skip + 3)),
+        _ => None,
+    }
+}
+
+/// Analizza una sequenza in stile tcsh (`^X^E`, `\e.`, `M-b`)
+pub fn parse_keys(spec: &str) -> Option<Vec<Key>> {
+    let chars: Vec<char> = spec.chars().collect();
+    let mut keys: Vec<Key> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let next = chars.get(i + 1).copied();
+        let key = if let Some((modifier, c, len)) = modified(&chars[i..]) {
+            i += len;
+            if modifier == 'M' { (KeyModifiers::ALT, KeyCode::Char(c)) } else { control(c)? }
+        } else {
+            match (chars[i], next) {
+                ('^', Some(c)) => { i += 2; control(c)? }
+                ('\\', Some(c)) => {
+                    i += 2;
+                    match c {
+                        'e' | 'E' => (KeyModifiers::NONE, KeyCode::Esc),
+                        't' => (KeyModifiers::NONE, KeyCode::Tab),
+                        'n' | 'r' => (KeyModifiers::NONE, KeyCode::Enter),
+                        c => (KeyModifiers::NONE, KeyCode::Char(c)),
+                    }
+                }
+                (c, _) => { i += 1; (KeyModifiers::NONE, KeyCode::Char(c)) }
+            }
+        };
+        // Esc seguito da un carattere arriva dal terminale come Alt+carattere
+        match (keys.last(), key) {
+            (Some(&(KeyModifiers::NONE, KeyCode::Esc)), (KeyModifiers::NONE, KeyCode::Char(c))) => {
+                keys.pop();
+                keys.push((KeyModifiers::ALT, KeyCode::Char(c)));
+            }
+            _ => keys.push(key),
+        }
+    }
+    (!keys.is_empty()).then_some(keys)
+}
+
+fn display_key(&(modifiers, code): &Key) -> String {
+    if let Some((name, _)) = NAMED_KEYS.iter().find(|(_, c)| *c == code) {
+        return format!("<{}>", name);
+    }
+    match code {
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::CONTROL) => format!("^{}", c.to_ascii_uppercase()),
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::ALT) => format!("M-{}", c),
+        KeyCode::Char('^') => "\\^".to_string(),
+        KeyCode::Char('\\') => "\\\\".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Backspace => "^?".to_string(),
+        KeyCode::Esc => "^[".to_string(),
+        KeyCode::Tab => "^I".to_string(),
+        KeyCode::Enter => "^M".to_string(),
+        KeyCode::F(n) => format!("<f{}>", n),
+        other => format!("{:?}", other),
+    }
+}
+
+pub fn display_keys(keys: &[Key]) -> String {
+    keys.iter().map(display_key).collect()
+}
+
+// Le maiuscole arrivano con SHIFT: lo togliamo per confrontarle con `bindkey`
+fn key_of(event: &Event) -> Option<Key> {
+    let Event::Key(KeyEvent { code, modifiers, .. }) = event else { return None; };
+    let modifiers = match code {
+        KeyCode::Char(_) => *modifiers - KeyModifiers::SHIFT,
+        _ => *modifiers,
+    };
+    Some((modifiers, *code))
+}
+
+// --- MODALITÀ DI EDITING CON LE SEQUENZE DI `bindkey` ---
+
+/// Avvolge la modalità emacs/vi di reedline, che conosce solo tasti singoli:
+/// intercetta le sequenze definite con `bindkey` e passa il resto all'originale
+pub struct BoundMode {
+    inner: Box<dyn EditMode>,
+    bindings: Vec<Binding>,
+    pending: Vec<Event>,
+}
+
+impl BoundMode {
+    pub fn new(inner: Box<dyn EditMode>) -> Self {
+        BoundMode { inner, bindings: BINDINGS.lock().unwrap().clone(), pending: Vec::new() }
+    }
+
+    // Restituisce all'editor originale i tasti trattenuti
+    fn replay(&mut self, events: Vec<Event>) -> ReedlineEvent {
+        let mut out: Vec<ReedlineEvent> = events
+            .into_iter()
+            .filter_map(ReedlineRawEvent::convert_from)
+            .map(|raw| self.inner.parse_event(raw))
+            .collect();
+        if out.len() == 1 { out.remove(0) } else { ReedlineEvent::Multiple(out) }
+    }
+}
+
+impl EditMode for BoundMode {
+    fn parse_event(&mut self, event: ReedlineRawEvent) -> ReedlineEvent {
+        let event = event.into();
+        if key_of(&event).is_none() {
+            return self.replay(vec![event]);
+        }
+
+        let vi_command = matches!(self.inner.edit_mode(), PromptEditMode::Vi(PromptViMode::Normal));
+        self.pending.push(event);
+        let typed: Vec<Key> = self.pending.iter().filter_map(key_of).collect();
+        let mut candidates = self.bindings
+            .iter()
+            .filter(|b| b.vi_command == vi_command && b.keys.starts_with(&typed));
+
+        // La sequenza completa vince su quelle più lunghe che la estendono
+        if let Some(binding) = candidates.clone().find(|b| b.keys.len() == typed.len()) {
+            self.pending.clear();
+            return match &binding.action {
+                Action::Widget(name) => widget(name).unwrap_or(ReedlineEvent::None),
+                Action::Command(command) => {
+                    *HOST_COMMAND.lock().unwrap() = Some(command.clone());
+                    ReedlineEvent::ExecuteHostCommand(command.clone())
+                }
+                Action::Unbound => ReedlineEvent::None,
+            };
+        }
+        if candidates.next().is_some() {
+            return ReedlineEvent::None;
+        }
+        let events = std::mem::take(&mut self.pending);
+        self.replay(events)
+    }
+
+    fn edit_mode(&self) -> PromptEditMode {
+        self.inner.edit_mode()
+    }
+}
+
+/// Torna ai soli tasti predefiniti (per `reload`)
+pub fn reset() {
+    *BINDINGS.lock().unwrap() = defaults();
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+// --- BUILTIN ---
+
+fn set_binding(binding: Binding) {
+    let mut bindings = BINDINGS.lock().unwrap();
+    bindings.retain(|b| b.keys != binding.keys || b.vi_command != binding.vi_command);
+    bindings.push(binding);
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+fn describe(binding: &Binding) -> String {
+    let action = match &binding.action {
+        Action::Widget(name) => name.clone(),
+        Action::Command(command) => format!("\"{}\"", command),
+        Action::Unbound => "undefined-key".to_string(),
+    };
+    let map = if binding.vi_command { "  (vi normal)" } else { "" };
+    format!("{:<16} ->  {}{}", format!("\"{}\"", display_keys(&binding.keys)), action, map)
+}
+
+pub fn bindkey(args: &[&str]) -> bool {
+    let (mut vi_command, mut is_command, mut remove, mut named) = (false, false, false, false);
+    let mut rest = args;
+    while let Some(flag) = rest.first().filter(|a| a.starts_with('-') && a.len() > 1) {
+        match *flag {
+            "-a" => vi_command = true,
+            "-c" => is_command = true,
+            "-r" => remove = true,
+            "-k" => named = true,
+            "-v" => { state::set_shell_var("editmode", "vi"); return true; }
+            "-e" => { state::set_shell_var("editmode", "emacs"); return true; }
+            "-l" => {
+                for (name, _) in WIDGETS { println!("{}", name); }
+                return true;
+            }
+            "--" => { rest = &rest[1..]; break; }
+            other => {
+                eprintln!("grsh: bindkey: opzione sconosciuta: {}", other);
+                return false;
+            }
+        }
+        rest = &rest[1..];
+    }
+
+    let Some(spec) = rest.first() else {
+        for binding in BINDINGS.lock().unwrap().iter() { println!("{}", describe(binding)); }
+        return true;
+    };
+    let keys = if named { named_key(spec).map(|code| vec![(KeyModifiers::NONE, code)]) } else { parse_keys(spec) };
+    let Some(keys) = keys else {
+        eprintln!("grsh: bindkey: sequenza non valida: {}", spec);
+        return false;
+    };
+
+    if remove {
+        set_binding(Binding { keys, vi_command, action: Action::Unbound });
+        return true;
+    }
+
+    let rest = &rest[1..];
+    if rest.is_empty() {
+        let bindings = BINDINGS.lock().unwrap();
+        match bindings.iter().find(|b| b.keys == keys && b.vi_command == vi_command) {
+            Some(binding) => println!("{}", describe(binding)),
+            None => println!("\"{}\" non è ridefinito", display_keys(&keys)),
+        }
+        return true;
+    }
+
+    let action = if is_command {
+        Action::Command(rest.join(" "))
+    } else if widget(rest[0]).is_some() {
+        Action::Widget(rest[0].to_string())
+    } else {
+        eprintln!("grsh: bindkey: azione sconosciuta: {} (vedi bindkey -l)", rest[0]);
+        return false;
+    };
+    set_binding(Binding { keys, vi_command, action });
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reedline::Emacs;
+
+    fn ctrl(c: char) -> Key {
+        (KeyModifiers::CONTROL, KeyCode::Char(c))
+    }
+
+    fn plain(code: KeyCode) -> Key {
+        (KeyModifiers::NONE, code)
+    }
+
+    fn press(mode: &mut BoundMode, (modifiers, code): Key) -> ReedlineEvent {
+        let raw = ReedlineRawEvent::convert_from(Event::Key(KeyEvent::new(code, modifiers))).unwrap();
+        mode.parse_event(raw)
+    }
+
+    #[test]
+    fn parse_sequences() {
+        assert_eq!(parse_keys("^X^E"), Some(vec![ctrl('x'), ctrl('e')]));
+        assert_eq!(parse_keys("^[").unwrap(), [plain(KeyCode::Esc)]);
+        assert_eq!(parse_keys("\\e").unwrap(), [plain(KeyCode::Esc)]);
+        // Esc seguito da un carattere è Alt+carattere, comunque lo si scriva
+        let alt_b = vec![(KeyModifiers::ALT, KeyCode::Char('b'))];
+        for spec in ["\\eb", "^[b", "M-b", "\\M-b"] {
+            assert_eq!(parse_keys(spec).as_ref(), Some(&alt_b), "{}", spec);
+        }
+        assert_eq!(parse_keys("C-x^?\\t^M").unwrap(), [ctrl('x'), plain(KeyCode::Backspace), plain(KeyCode::Tab), plain(KeyCode::Enter)]);
+        assert_eq!(parse_keys("\\^a").unwrap(), [plain(KeyCode::Char('^')), plain(KeyCode::Char('a'))]);
+        assert_eq!(display_keys(&parse_keys("^X\\e.").unwrap()), "^XM-.");
+    }
+
+    #[test]
+    fn invalid_sequences() {
+        for spec in ["", "^1", "^ ", "C-1", "^X^!"] {
+            assert_eq!(parse_keys(spec), None, "{}", spec);
+        }
+    }
+
+    #[test]
+    fn multi_key_prefixes() {
+        let bind = |keys: Vec<Key>, action: Action| Binding { keys, vi_command: false, action };
+        let mut mode = BoundMode {
+            inner: Box::new(Emacs::default()),
+            bindings: vec![
+                bind(vec![ctrl('x'), ctrl('e')], Action::Widget("edit-command-line".to_string())),
+                bind(vec![ctrl('x'), ctrl('r'), ctrl('r')], Action::Widget("clear-screen".to_string())),
+                bind(vec![ctrl('x'), ctrl('r')], Action::Widget("undo".to_string())),
+                bind(vec![ctrl('g')], Action::Unbound),
+            ],
+            pending: Vec::new(),
+        };
+
+        // Un prefisso resta in attesa, la sequenza completa esegue l'azione
+        assert_eq!(press(&mut mode, ctrl('x')), ReedlineEvent::None);
+        assert_eq!(press(&mut mode, ctrl('e')), ReedlineEvent::OpenEditor);
+        assert!(mode.pending.is_empty());
+
+        // Una sequenza completa vince su una più lunga che la estende
+        press(&mut mode, ctrl('x'));
+        assert_eq!(press(&mut mode, ctrl('r')), edit(EditCommand::Undo));
+
+        // Se la sequenza non porta a nulla, i tasti trattenuti tornano all'editor
+        press(&mut mode, ctrl('x'));
+        let replayed = press(&mut mode, plain(KeyCode::Char('a')));
+        assert!(matches!(&replayed, ReedlineEvent::Multiple(events) if events.len() == 2), "{:?}", replayed);
+        assert!(mode.pending.is_empty());
+        assert_eq!(press(&mut mode, plain(KeyCode::Char('a'))), edit(EditCommand::InsertChar('a')));
+
+        // Un tasto tolto con `bindkey -r` non fa nulla
+        assert_eq!(press(&mut mode, ctrl('g')), ReedlineEvent::None);
+    }
+
+    // `bindkey` cambia i tasti e le variabili globali: un solo test, in sequenza
+    #[test]
+    fn bindkey_builtin() {
+        let find = |keys: &[Key]| BINDINGS.lock().unwrap().iter().find(|b| b.keys == keys && !b.vi_command).map(|b| b.action.clone());
+
+        assert!(bindkey(&["-c", "^Xg", "git", "status"]));
+        assert_eq!(find(&[ctrl('x'), plain(KeyCode::Char('g'))]), Some(Action::Command("git status".to_string())));
+        assert!(bindkey(&["^G", "clear-screen"]));
+        assert_eq!(find(&[ctrl('g')]), Some(Action::Widget("clear-screen".to_string())));
+
+        let before = generation();
+        assert!(bindkey(&["-r", "^G"]));
+        assert_eq!(find(&[ctrl('g')]), Some(Action::Unbound));
+        assert!(generation() > before);
+        // Anche un tasto che grsh non ridefiniva
+        assert!(bindkey(&["-r", "^T"]));
+        assert_eq!(find(&[ctrl('t')]), Some(Action::Unbound));
+
+        assert!(!bindkey(&["^G", "azione-inesistente"]));
+        assert!(!bindkey(&["-r", "^1"]));
+        assert!(!bindkey(&["-q"]));
+
+        reset();
+        assert_eq!(find(&[ctrl('g')]), Some(Action::Widget("autosuggest-accept".to_string())));
+        assert_eq!(find(&[ctrl('t')]), None);
+
+        // `bindkey -v`/`-e` impostano `editmode` nella shell, senza esportarla
+        assert!(bindkey(&["-v"]));
+        assert_eq!(state::get_var("editmode").as_deref(), Some("vi"));
+        assert!(std::env::var_os("editmode").is_none());
+        assert!(bindkey(&["-e"]));
+        assert_eq!(state::get_var("editmode").as_deref(), Some("emacs"));
+        assert!(std::env::var_os("editmode").is_none());
+        state::unset_var("editmode");
+    }
+}
@@ -0,0 +1,438 @@
+use std::iter::Peekable;
+use std::str::Chars;
+use crate::shell::{glob, osstr};
+
+// Caratteri che il completer deve proteggere con `\` nei nomi inseriti
+const SHELL_SPECIAL: &[char] = &[
+    ' ', '\t', '\\', '\'', '"', '$', '&', ';', '|', '<', '>', '(', ')',
+    '*', '?', '[', ']', '{', '}', '!', '`',
+];
+
+// --- SCANSIONE CONSAPEVOLE DELLE VIRGOLETTE ---
+
+/// Chiama `visit` per ogni carattere non quotato e non protetto da `\`
+/// (con la sua posizione in byte); si ferma quando `visit` restituisce `true`.
+/// Restituisce `true` se il testo termina dentro delle virgolette ancora aperte.
+fn scan_unquoted(s: &str, mut visit: impl FnMut(usize, char) -> bool) -> bool {
+    let mut chars = s.char_indices().peekable();
+    let (mut single, mut double, mut ansi) = (false, false, false);
+    while let Some((i, c)) = chars.next() {
+        if single {
+            if c == '\'' { single = false; }
+            continue;
+        }
+        if ansi || double {
+            match c {
+                '\\' => { chars.next(); }
+                '\'' if ansi => ansi = false,
+                '"' if double => double = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '\\' => { chars.next(); }
+            '\'' => single = true,
+            '"' => double = true,
+            '$' if chars.peek().map(|&(_, n)| n) == Some('\'') => {
+                chars.next();
+                ansi = true;
+            }
+            _ => if visit(i, c) { return false; },
+        }
+    }
+    single || double || ansi
+}
+
+/// Posizione della prima occorrenza di `pat` fuori da virgolette ed escape
+pub fn find_unquoted(s: &str, pat: &str) -> Option<usize> {
+    let mut found = None;
+    let _ = scan_unquoted(s, |i, _| {
+        if s[i..].starts_with(pat) { found = Some(i); true } else { false }
+    });
+    found
+}
+
+/// Divide la riga sui `sep` non quotati e non annidati in parentesi (es. `@(a|b)`)
+pub fn split_unquoted(s: &str, sep: char) -> Vec<String> {
+    let mut cuts = Vec::new();
+    let mut depth = 0usize;
+    let _ = scan_unquoted(s, |i, c| {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ if c == sep && depth == 0 => cuts.push(i),
+            _ => {}
+        }
+        false
+    });
+
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for cut in cuts {
+        parts.push(s[start..cut].to_string());
+        start = cut + sep.len_utf8();
+    }
+    parts.push(s[start..].to_string());
+    parts
+}
+
+/// Vero se la riga termina con un `\` che continua sulla riga successiva
+pub fn ends_with_continuation(line: &str) -> bool {
+    let trailing = line.chars().rev().take_while(|&c| c == '\\').count();
+    trailing % 2 == 1
+}
+
+/// Motivo per cui un comando non è ancora completo
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Incomplete {
+    /// Virgolette (`'`, `"` o `$'`) ancora aperte
+    Quote,
+    /// `\` finale
+    Backslash,
+    /// `|`, `||` o `&&` finale
+    Operator,
+}
+
+/// Controlla se il testo richiede altre righe per formare un comando completo
+pub fn incomplete(text: &str) -> Option<Incomplete> {
+    if scan_unquoted(text, |_, _| false) { return Some(Incomplete::Quote); }
+    if ends_with_continuation(text) { return Some(Incomplete::Backslash); }
+
+    let trimmed = text.trim_end();
+    let op_len = if trimmed.ends_with("&&") { 2 } else if trimmed.ends_with('|') { 1 } else { 0 };
+    if op_len > 0 && !ends_with_continuation(&trimmed[..trimmed.len() - op_len]) {
+        return Some(Incomplete::Operator);
+    }
+    None
+}
+
+/// Divide il testo in comandi logici, unendo le righe continuate con `\`,
+/// quelle che terminano con `|`/`&&` e le stringhe quotate su più righe.
+pub fn logical_lines(content: &str) -> Vec<String> {
+    numbered_lines(content).into_iter().map(|(_, line)| line).collect()
+}
+
+/// Come `logical_lines`, con il numero (da 1) della prima riga fisica di ognuna
+pub fn numbered_lines(content: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut pending = String::new();
+    let mut start = 1;
+    for (index, line) in content.lines().enumerate() {
+        if pending.is_empty() { start = index + 1; }
+        pending.push_str(line);
+        match incomplete(&pending) {
+            Some(Incomplete::Backslash) => { pending.pop(); }
+            Some(Incomplete::Quote) => pending.push('\n'),
+            Some(Incomplete::Operator) => pending.push(' '),
+            None => lines.push((start, std::mem::take(&mut pending))),
+        }
+    }
+    if !pending.is_empty() { lines.push((start, pending)); }
+    lines
+}
+
+/// Testo dopo `if` in `if (cond) ...` o `if(cond) ...`, `None` se la riga non è un `if`
+pub fn if_header(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix("if")?;
+    (rest.is_empty() || rest.starts_with(|c: char| c.is_whitespace() || c == '(')).then_some(rest)
+}
+
+/// Effetto di una riga sui blocchi in stile tcsh: `Some(true)` se ne apre uno
+/// (`foreach`, `if (...) then`), `Some(false)` se lo chiude (`end`, `endif`)
+pub fn block_step(line: &str) -> Option<bool> {
+    // `if (cond) cmd` su una riga sola non apre un blocco
+    if let Some(rest) = if_header(line) {
+        return smart_split(rest).last().filter(|w| *w == "then").map(|_| true);
+    }
+    let words = smart_split(line.trim());
+    match words.first()?.as_str() {
+        "foreach" => Some(true),
+        "end" | "endif" => Some(false),
+        _ => None,
+    }
+}
+
+/// Riunisce ogni blocco `foreach ... end` e `if ... endif` (anche annidati) in un solo
+/// comando su più righe, numerato con la riga che lo apre: va eseguito per intero,
+/// non riga per riga
+pub fn group_blocks(lines: Vec<(usize, String)>) -> Vec<(usize, String)> {
+    let mut out = Vec::new();
+    let mut block: Option<(usize, String)> = None;
+    let mut depth = 0usize;
+    for (number, line) in lines {
+        let step = block_step(&line);
+        match block.as_mut() {
+            None if step == Some(true) => {
+                depth = 1;
+                block = Some((number, line));
+            }
+            None => out.push((number, line)),
+            Some((_, text)) => {
+                match step {
+                    Some(true) => depth += 1,
+                    Some(false) => depth -= 1,
+                    None => {}
+                }
+                text.push('\n');
+                text.push_str(&line);
+                if depth == 0 { out.extend(block.take()); }
+            }
+        }
+    }
+    // Blocco senza `end`/`endif`: lo segnala chi lo esegue
+    out.extend(block);
+    out
+}
+
+// --- TOKENIZER ---
+
+/// Divide la riga in parole gestendo `'...'`, `"..."`, `$'...'` e gli escape con `\`.
+/// Le parole restituite sono già in forma di pattern: i caratteri quotati o protetti
+/// sono preceduti da `\` e non verranno espansi dal globbing (vedi `glob::unescape`).
+pub fn smart_split(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => {
+                if in_word { args.push(std::mem::take(&mut current)); }
+                in_word = false;
+            }
+            '\\' => match chars.next() {
+                // Continuazione di riga: `\` + a capo spariscono
+                Some('\n') => {}
+                Some(n) => { current.push_str(&glob::escape(&n.to_string())); in_word = true; }
+                None => { current.push_str("\\\\"); in_word = true; }
+            },
+            '\'' => {
+                for n in chars.by_ref() {
+                    if n == '\'' { break; }
+                    current.push_str(&glob::escape(&n.to_string()));
+                }
+                in_word = true;
+            }
+            '"' => {
+                while let Some(n) = chars.next() {
+                    match n {
+                        '"' => break,
+                        '\\' => match chars.peek() {
+                            Some(&e @ ('$' | '`' | '"' | '\\')) => {
+                                chars.next();
+                                current.push_str(&glob::escape(&e.to_string()));
+                            }
+                            Some('\n') => { chars.next(); }
+                            _ => current.push_str("\\\\"),
+                        },
+                        _ => current.push_str(&glob::escape(&n.to_string())),
+                    }
+                }
+                in_word = true;
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                current.push_str(&glob::escape(&read_ansi_c(&mut chars)));
+                in_word = true;
+            }
+            _ => { current.push(c); in_word = true; }
+        }
+    }
+    if in_word { args.push(current); }
+    args
+}
+
+// --- ANSI-C QUOTING: $'...' ---
+
+/// Legge il contenuto di `$'...'` (apertura già consumata) interpretando gli escape
+fn read_ansi_c(chars: &mut Peekable<Chars>) -> String {
+    let mut out = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => break,
+            '\\' => match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some('r') => out.push('\r'),
+                Some('a') => out.push('\x07'),
+                Some('b') => out.push('\x08'),
+                Some('e') | Some('E') => out.push('\x1b'),
+                Some('f') => out.push('\x0c'),
+                Some('v') => out.push('\x0b'),
+                Some('x') => {
+                    if let Some((b, _)) = read_digits(chars, 16, 2) { osstr::push_byte(&mut out, b as u8); }
+                }
+                Some('u') if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    let code = read_digits(chars, 16, 8);
+                    if chars.peek() == Some(&'}') { chars.next(); }
+                    push_code(&mut out, code);
+                }
+                Some('u') => push_code(&mut out, read_digits(chars, 16, 4)),
+                Some('U') => push_code(&mut out, read_digits(chars, 16, 8)),
+                Some('c') => {
+                    if let Some(x) = chars.next() { out.push(char::from(x as u8 & 0x1f)); }
+                }
+                Some(d @ '0'..='7') => {
+                    let rest = read_digits(chars, 8, 2);
+                    let first = d.to_digit(8).unwrap_or(0);
+                    let code = match rest {
+                        Some((v, n)) => first * 8u32.pow(n) + v,
+                        None => first,
+                    };
+                    osstr::push_byte(&mut out, (code & 0xff) as u8);
+                }
+                Some(other) => {
+                    // `\\`, `\'`, `\"`, `\?` e sequenze sconosciute (conservate come in bash)
+                    if !matches!(other, '\\' | '\'' | '"' | '?') { out.push('\\'); }
+                    out.push(other);
+                }
+                None => out.push('\\'),
+            },
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Legge fino a `max` cifre nella base indicata; restituisce (valore, cifre lette)
+fn read_digits(chars: &mut Peekable<Chars>, radix: u32, max: u32) -> Option<(u32, u32)> {
+    let mut value = 0u32;
+    let mut count = 0;
+    while count < max {
+        match chars.peek().and_then(|c| c.to_digit(radix)) {
+            Some(d) => {
+                value = value * radix + d;
+                count += 1;
+                chars.next();
+            }
+            None => break,
+        }
+    }
+    if count == 0 { None } else { Some((value, count)) }
+}
+
+fn push_code(out: &mut String, code: Option<(u32, u32)>) {
+    if let Some(c) = code.and_then(|(v, _)| char::from_u32(v)) {
+        osstr::push_char(out, c);
+    }
+}
+
+// --- ESCAPE PER IL COMPLETER ---
+
+/// Protegge con `\` i caratteri speciali di un nome da inserire nella riga di comando.
+/// I byte non UTF-8 (codificati con `osstr`) diventano `$'\xNN'`, così restano esatti.
+pub fn escape_word(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut in_bytes = false;
+    for (i, c) in name.chars().enumerate() {
+        if let Some(b) = osstr::raw_byte(c) {
+            if !in_bytes { out.push_str("$'"); in_bytes = true; }
+            out.push_str(&format!("\\x{:02x}", b));
+            continue;
+        }
+        if in_bytes { out.push('\''); in_bytes = false; }
+        if SHELL_SPECIAL.contains(&c) || (i == 0 && (c == '~' || c == '#')) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    if in_bytes { out.push('\''); }
+    out
+}
+
+/// Inizio (in byte) della parola che termina in `pos`, ignorando gli spazi protetti
+pub fn word_start(line: &str, pos: usize) -> usize {
+    let mut start = 0;
+    let _ = scan_unquoted(&line[..pos], |i, c| {
+        if c == ' ' || c == '|' { start = i + 1; }
+        false
+    });
+    start
+}
+
+/// Testo letterale di una parola parziale (rimuove virgolette ed escape)
+pub fn unescape_word(word: &str) -> String {
+    smart_split(word)
+        .first()
+        .map(|w| glob::unescape(w))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    // Parole di `smart_split` come testo letterale, senza gli escape per il glob
+    fn words(line: &str) -> Vec<String> {
+        smart_split(line).iter().map(|w| glob::unescape(w)).collect()
+    }
+
+    fn ansi_c(quoted: &str) -> String {
+        read_ansi_c(&mut quoted.chars().peekable())
+    }
+
+    #[test]
+    fn smart_split_quotes() {
+        assert_eq!(words("  echo\tx  'a b'\t\"c \\\" d\" "), ["echo", "x", "a b", "c \" d"]);
+        assert_eq!(words("a'b'\"c\"d ''"), ["abcd", ""]);
+        assert_eq!(words("\"\\n \\$x\""), ["\\n $x"]);
+        // I caratteri jolly restano tali solo fuori dalle virgolette
+        assert_eq!(smart_split("*.rs '*.rs' \\*"), ["*.rs", "\\*.rs", "\\*"]);
+        assert_eq!(words("aperta 'non chiusa"), ["aperta", "non chiusa"]);
+        assert!(smart_split(" \t ").is_empty());
+    }
+
+    #[test]
+    fn ansi_c_escapes() {
+        assert_eq!(ansi_c("\\x41\\n'"), "A\n");
+        assert_eq!(words("$'\\x41\\n'b"), ["A\nb"]);
+        assert_eq!(ansi_c("\\e[1m\\t\\101\\u00e8\\u{1F600}\\cA'"), "\x1b[1m\tAè😀\x01");
+        assert_eq!(ansi_c("\\'\\\\\\\"'resto"), "'\\\"");
+        // Sequenze non valide: restano come in bash o vengono scartate se vuote
+        assert_eq!(ansi_c("\\q\\xZ'"), "\\qZ");
+        assert_eq!(ansi_c("\\u{110000}'"), "");
+        assert_eq!(ansi_c("senza fine\\"), "senza fine\\");
+    }
+
+    #[test]
+    fn escape_round_trip() {
+        let raw = osstr::encode(OsStr::from_bytes(b"caf\xe9 \xff.txt"));
+        for name in ["a b", "it's", "$HOME", "#nota", "~casa", "q*?[x]{y}", "a\\b\"c", "è ok", raw.as_str()] {
+            let escaped = escape_word(name);
+            assert_eq!(smart_split(&escaped).len(), 1, "{}", escaped);
+            assert_eq!(unescape_word(&escaped), name);
+        }
+        assert_eq!(escape_word("a b&c"), "a\\ b\\&c");
+        assert_eq!(escape_word("x~#"), "x~#");
+        assert_eq!(escape_word(&raw), "caf$'\\xe9'\\ $'\\xff'.txt");
+    }
+
+    #[test]
+    fn line_continuation() {
+        assert_eq!(words("echo a\\\nb"), ["echo", "ab"]);
+        assert_eq!(words("\"a\\\nb\""), ["ab"]);
+        assert!(ends_with_continuation("ls \\"));
+        assert!(!ends_with_continuation("ls \\\\"));
+        assert_eq!(incomplete("ls |"), Some(Incomplete::Operator));
+        assert_eq!(incomplete("ls \\|"), None);
+        assert_eq!(incomplete("echo 'a"), Some(Incomplete::Quote));
+        assert_eq!(
+            numbered_lines("echo a \\\n  b\nls |\nwc\necho 'x\ny'\npwd"),
+            [(1, "echo a   b".to_string()), (3, "ls | wc".to_string()), (5, "echo 'x\ny'".to_string()), (7, "pwd".to_string())],
+        );
+    }
+
+    #[test]
+    fn if_headers_with_or_without_space() {
+        assert_eq!(if_header("if (a == b) then"), Some(" (a == b) then"));
+        assert_eq!(if_header("if(a == b) echo"), Some("(a == b) echo"));
+        assert_eq!(if_header("ifconfig -a"), None);
+        assert_eq!(block_step("if(-d /tmp) then"), Some(true));
+        assert_eq!(block_step("if(-d /tmp) echo"), None);
+    }
+}
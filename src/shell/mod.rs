@@ -5,3 +5,22 @@ pub mod builtins;
 pub mod exec;
 pub mod alias;
 pub mod state;
+pub mod glob;
+pub mod globqual;
+pub mod lexer;
+pub mod cond;
+pub mod osstr;
+pub mod validator;
+pub mod histexpand;
+pub mod history;
+pub mod histfilter;
+pub mod resolver;
+pub mod correct;
+pub mod editmode;
+pub mod keybind;
+pub mod fc;
+pub mod prompt;
+pub mod git;
+pub mod theme;
+pub mod toolchain;
+pub mod config;
@@ -0,0 +1,116 @@
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+// --- NOMI NON UTF-8 ---
+// Lexer, globbing e completer lavorano su `String`: i byte che non formano UTF-8
+// valido vengono rappresentati con i caratteri U+10FF80..U+10FFFF (area privata)
+// e riconvertiti nei byte originali solo al confine con il sistema operativo.
+// Un carattere vero di quell'intervallo viene codificato come i suoi quattro byte
+// UTF-8, così il percorso inverso è esatto. Tutto il testo che entra nella shell
+// (riga digitata, script, `-c`) passa da `encode`/`encode_str`.
+
+const BYTE_BASE: u32 = 0x10FF00;
+
+fn byte_char(b: u8) -> char {
+    char::from_u32(BYTE_BASE + b as u32).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+/// Byte grezzo rappresentato da `c`, se è uno dei caratteri di codifica
+pub fn raw_byte(c: char) -> Option<u8> {
+    let code = c as u32;
+    if (BYTE_BASE + 0x80..=BYTE_BASE + 0xff).contains(&code) {
+        Some((code - BYTE_BASE) as u8)
+    } else {
+        None
+    }
+}
+
+/// Aggiunge a `out` un byte letterale (es. da `$'\xff'`)
+pub fn push_byte(out: &mut String, b: u8) {
+    if b < 0x80 { out.push(b as char); } else { out.push(byte_char(b)); }
+}
+
+/// Aggiunge a `out` un carattere di testo esterno (es. da `$'\u{...}'`)
+pub fn push_char(out: &mut String, c: char) {
+    if raw_byte(c).is_some() {
+        let mut buf = [0u8; 4];
+        for &b in c.encode_utf8(&mut buf).as_bytes() { out.push(byte_char(b)); }
+    } else {
+        out.push(c);
+    }
+}
+
+/// Converte un `OsStr` nella rappresentazione interna senza perdere byte
+pub fn encode(os: &OsStr) -> String {
+    let mut out = String::with_capacity(os.len());
+    for chunk in os.as_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() { push_char(&mut out, c); }
+        for &b in chunk.invalid() { out.push(byte_char(b)); }
+    }
+    out
+}
+
+/// Come `encode`, per testo già UTF-8 (la riga digitata nell'editor)
+pub fn encode_str(s: &str) -> String {
+    encode(OsStr::new(s))
+}
+
+/// Legge uno script byte per byte, nella rappresentazione interna
+pub fn read_file(path: &Path) -> io::Result<String> {
+    fs::read(path).map(|bytes| encode(OsStr::from_bytes(&bytes)))
+}
+
+/// Riporta la rappresentazione interna ai byte originali
+pub fn decode(s: &str) -> OsString {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut buf = [0u8; 4];
+    for c in s.chars() {
+        match raw_byte(c) {
+            Some(b) => bytes.push(b),
+            None => bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+        }
+    }
+    OsString::from_vec(bytes)
+}
+
+/// Versione stampabile: i byte non UTF-8 diventano `\xNN`
+pub fn display(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match raw_byte(c) {
+            Some(b) => out.push_str(&format!("\\x{:02x}", b)),
+            None => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_is_byte_exact() {
+        // U+10FFAA è valido in UTF-8 ma cade nell'intervallo usato per i byte grezzi
+        let mut bytes = "a\u{10FFAA}b".as_bytes().to_vec();
+        bytes.extend_from_slice(&[0xff, 0xfe, b'.', 0xc3]);
+        let original = OsString::from_vec(bytes);
+        let encoded = encode(&original);
+        assert_eq!(decode(&encoded), original);
+        assert_eq!(display(&encoded), "a\\xf4\\x8f\\xbe\\xaab\\xff\\xfe.\\xc3");
+    }
+
+    #[test]
+    fn literal_chars_round_trip() {
+        let mut out = String::new();
+        push_char(&mut out, '\u{10FFFF}');
+        push_byte(&mut out, 0x80);
+        let mut expected = "\u{10FFFF}".as_bytes().to_vec();
+        expected.push(0x80);
+        assert_eq!(decode(&out), OsString::from_vec(expected));
+        assert_eq!(decode(&encode_str("plain ✓")), OsStr::new("plain ✓"));
+    }
+}
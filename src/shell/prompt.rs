@@ -1,43 +1,261 @@
-use reedline::{Prompt, PromptHistorySearch, PromptHistorySearchStatus};
-use nu_ansi_term::{Color, Style};
-use std::borrow::Cow;
 use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use chrono::Local;
+use lazy_static::lazy_static;
+use nu_ansi_term::Color;
+use nix::sys::signal::Signal;
+use nix::unistd::{self, User};
+use crate::shell::{history, osstr, state, theme};
 
-pub struct GrshPrompt {
-    pub prompt_str: String,
-}
-
-impl Prompt for GrshPrompt {
-    fn render_prompt_left(&self) -> Cow<str> {
-        // Se il prompt_str è vuoto o di default, costruiamo quello richiesto
-        if self.prompt_str.is_empty() {
-            let user = env::var("USER").unwrap_or_else(|_| "user".into());
-            let host = "server"; // Potresti usare gethostname crate
-            let pwd = env::current_dir()
-                .unwrap_or_else(|_| "/".into())
-                .display()
-                .to_string();
-
-            let user_color = if user == "root" { Color::Red } else { Color::Green };
-            let at_color = Color::Green;
-            let pwd_color = Color::Blue;
-
-            let res = format!(
-                "{}{}{} {} ➜ ",
-                user_color.bold().paint(user),
-                at_color.paint("@"),
-                at_color.paint(host),
-                pwd_color.bold().paint(pwd),
-            );
-            return Cow::Owned(res);
+// --- PROMPT CONFIGURABILE: `set prompt`, `set prompt2`, `set rprompt` ---
+// Il modello accetta sia gli escape `%` di tcsh sia quelli `\` di bash:
+//   %n \u utente         %m \h host          %M \H host completo
+//   %~ \w directory      %/ directory senza ~  %c %. \W ultima componente
+//   %# \$ # per root     %T \A ora (24h)      %t %@ \@ ora (12h)
+//   %P \t con secondi    %h %! \! numero in cronologia   %? stato   %j \j job
+//   %E durata dell'ultimo comando
+//   %B %b grassetto      %U %u sottolineato   %S %s inverso
+//   %{...%} e \[...\] racchiudono sequenze di escape (\e, \033): il contenuto resta
+//   com'è e i delimitatori spariscono, perché reedline misura da solo la larghezza
+//   del prompt escludendo le sequenze di escape. Il testo stampabile tra i
+//   delimitatori conta invece come larghezza.
+
+pub fn user() -> String {
+    env::var("USER").ok()
+        .or_else(|| User::from_uid(unistd::geteuid()).ok().flatten().map(|u| u.name))
+        .unwrap_or_else(|| "user".to_string())
+}
+
+lazy_static! {
+    // Il nome host non cambia durante la sessione: una sola chiamata di sistema
+    static ref HOSTNAME: String = unistd::gethostname()
+        .map(|h| h.to_string_lossy().into_owned())
+        .unwrap_or_default();
+}
+
+/// Nome host, breve (fino al primo punto) o completo
+pub fn host(full: bool) -> String {
+    if full { HOSTNAME.clone() } else { HOSTNAME.split('.').next().unwrap_or_default().to_string() }
+}
+
+fn current_dir() -> PathBuf {
+    env::current_dir().unwrap_or_else(|_| PathBuf::from("/"))
+}
+
+/// Directory corrente con la home abbreviata in `~`
+pub fn tilde_dir() -> String {
+    let dir = current_dir();
+    match dirs::home_dir().and_then(|home| dir.strip_prefix(&home).ok().map(Path::to_path_buf)) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", osstr::encode(rest.as_os_str())),
+        None => osstr::encode(dir.as_os_str()),
+    }
+}
+
+// Ultima componente della directory (`~` nella home, `/` nella radice)
+fn trailing_dir(tilde: bool) -> String {
+    let dir = current_dir();
+    if tilde && dirs::home_dir().is_some_and(|home| home == dir) {
+        return "~".to_string();
+    }
+    dir.file_name()
+        .map(osstr::encode)
+        .unwrap_or_else(|| "/".to_string())
+}
+
+fn is_root() -> bool {
+    unistd::geteuid().is_root()
+}
+
+pub fn time(format: &str) -> String {
+    Local::now().format(format).to_string()
+}
+
+// Numero in cronologia del prossimo comando, 0 se da ricalcolare: reedline ridisegna
+// il prompt a ogni tasto, la cronologia si conta una volta per prompt
+static HISTORY_NUMBER: AtomicUsize = AtomicUsize::new(0);
+
+/// Da chiamare prima di ogni prompt: i valori letti una volta per prompt si ricalcolano
+pub fn invalidate() {
+    HISTORY_NUMBER.store(0, Ordering::Relaxed);
+}
+
+fn history_number() -> String {
+    let mut number = HISTORY_NUMBER.load(Ordering::Relaxed);
+    if number == 0 {
+        number = history::count() + 1;
+        HISTORY_NUMBER.store(number, Ordering::Relaxed);
+    }
+    number.to_string()
+}
+
+pub fn exit_status() -> String {
+    state::get_var("?").unwrap_or_else(|| "0".to_string())
+}
+
+/// Stato dell'ultimo comando se è fallito, con il nome del segnale che l'ha terminato: `130 INT`
+pub fn failed_status() -> Option<String> {
+    let code = state::last_status();
+    if code == 0 { return None; }
+    let signal = (code > 128).then(|| Signal::try_from(code - 128).ok()).flatten();
+    Some(match signal {
+        Some(signal) => format!("{} {}", code, signal.as_str().trim_start_matches("SIG")),
+        None => code.to_string(),
+    })
+}
+
+/// Durata leggibile: `850ms`, `12.3s`, `4m 05s`, `1h 02m`
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    match secs {
+        0 => format!("{}ms", d.as_millis()),
+        1..=59 => format!("{:.1}s", d.as_secs_f64()),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+fn duration() -> String {
+    state::last_duration().map(format_duration).unwrap_or_default()
+}
+
+fn jobs() -> String {
+    state::get_jobs().len().to_string()
+}
+
+// Escape `%x` di tcsh
+fn percent(c: char) -> Option<String> {
+    Some(match c {
+        'n' => user(),
+        'm' => host(false),
+        'M' => host(true),
+        '~' => tilde_dir(),
+        '/' => osstr::encode(current_dir().as_os_str()),
+        'c' | '.' => trailing_dir(true),
+        'C' => trailing_dir(false),
+        '#' => if is_root() { "#" } else { ">" }.to_string(),
+        'T' => time("%H:%M"),
+        't' | '@' => time("%l:%M%P"),
+        'P' => time("%H:%M:%S"),
+        'p' => time("%l:%M:%S%P"),
+        'd' => time("%a"),
+        'D' => time("%d"),
+        'w' => time("%b"),
+        'W' => time("%m"),
+        'y' => time("%y"),
+        'Y' => time("%Y"),
+        'h' | '!' => history_number(),
+        '?' => exit_status(),
+        'j' => jobs(),
+        'E' => duration(),
+        'B' => "\x1b[1m".to_string(),
+        'b' => "\x1b[22m".to_string(),
+        'U' => "\x1b[4m".to_string(),
+        'u' => "\x1b[24m".to_string(),
+        'S' => "\x1b[7m".to_string(),
+        's' => "\x1b[27m".to_string(),
+        '%' => "%".to_string(),
+        // Il contenuto resta: reedline esclude da solo le sequenze di escape dalla larghezza
+        '{' | '}' => String::new(),
+        // Stato del parser in `prompt2`: non lo teniamo
+        'R' => String::new(),
+        _ => return None,
+    })
+}
+
+// Escape `\x` di bash
+fn backslash(c: char) -> Option<String> {
+    Some(match c {
+        'u' => user(),
+        'h' => host(false),
+        'H' => host(true),
+        'w' => tilde_dir(),
+        'W' => trailing_dir(true),
+        '$' => if is_root() { "#" } else { "$" }.to_string(),
+        't' => time("%H:%M:%S"),
+        'T' => time("%I:%M:%S"),
+        '@' => time("%I:%M %p"),
+        'A' => time("%H:%M"),
+        'd' => time("%a %b %d"),
+        '!' | '#' => history_number(),
+        'j' => jobs(),
+        's' => "grsh".to_string(),
+        'v' | 'V' => env!("CARGO_PKG_VERSION").to_string(),
+        'n' => "\n".to_string(),
+        'e' => "\x1b".to_string(),
+        'a' => "\x07".to_string(),
+        '\\' => "\\".to_string(),
+        '[' | ']' => String::new(),
+        _ => return None,
+    })
+}
+
+/// Espande un modello di prompt
+pub fn expand(template: &str) -> String {
+    let chars: Vec<char> = template.chars().collect();
+    let mut out = String::with_capacity(template.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match (c, next) {
+            // `\033`: carattere in ottale
+            ('\\', Some('0'..='7')) => {
+                let digits: String = chars[i + 1..].iter().take(3).take_while(|d| d.is_digit(8)).collect();
+                if let Some(ch) = u32::from_str_radix(&digits, 8).ok().and_then(char::from_u32) {
+                    out.push(ch);
+                }
+                i += 1 + digits.len();
+                continue;
+            }
+            ('%', Some(n)) => match percent(n) {
+                Some(text) => out.push_str(&text),
+                None => { out.push(c); out.push(n); }
+            },
+            ('\\', Some(n)) => match backslash(n) {
+                Some(text) => out.push_str(&text),
+                None => { out.push(c); out.push(n); }
+            },
+            _ => { out.push(c); i += 1; continue; }
         }
-        
-        // Qui andrebbe la logica per interpretare variabili tipo \u, \h, \w dal .grshrc
-        Cow::Owned(self.prompt_str.clone())
+        i += 2;
+    }
+    out
+}
+
+/// Prompt a destra predefinito (`set rprompt` senza valore): stato se diverso da 0,
+/// durata oltre `duration_threshold`, job sospesi e ora
+pub fn default_right() -> String {
+    let mut segments = Vec::new();
+    if let Some(status) = failed_status() {
+        segments.push(Color::Red.bold().paint(format!("✘ {}", status)).to_string());
+    }
+    if let Some(d) = state::last_duration().filter(|d| *d >= theme::duration_threshold()) {
+        segments.push(Color::Yellow.paint(format_duration(d)).to_string());
     }
+    let jobs = state::get_jobs().len();
+    if jobs > 0 {
+        segments.push(Color::Yellow.bold().paint(format!("[{}]", jobs)).to_string());
+    }
+    segments.push(Color::DarkGray.paint(time("%H:%M:%S")).to_string());
+    segments.join(" ")
+}
+
+/// Modello impostato nella variabile `name`, se presente e non vuoto
+pub fn template(name: &str) -> Option<String> {
+    state::get_var(name).filter(|t| !t.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    fn render_prompt_right(&self) -> Cow<str> { Cow::Borrowed("") }
-    fn render_prompt_indicator(&self, _edit_mode: reedline::EditMode) -> Cow<str> { Cow::Borrowed("") }
-    fn render_prompt_multiline_indicator(&self) -> Cow<str> { Cow::Borrowed("::: ") }
-    fn render_history_search_indicator(&self, _history_search: PromptHistorySearch) -> Cow<str> { Cow::Borrowed("search: ") }
+    #[test]
+    fn zero_width_markers_keep_their_contents() {
+        assert_eq!(expand("%{\\033[1;31m%}x%{\\e[0m%}"), "\x1b[1;31mx\x1b[0m");
+        assert_eq!(expand("\\[\\e]0;titolo\\a\\]$ "), "\x1b]0;titolo\x07$ ");
+        assert_eq!(expand("100%% %q"), "100% %q");
+    }
 }
@@ -1,45 +1,21 @@
 use reedline::{
-    ColumnarMenu, Emacs,
-    KeyCode, KeyModifiers, Reedline, ReedlineEvent, ReedlineMenu,
-    Signal, FileBackedHistory, MenuBuilder,
+    ColumnarMenu, Reedline, ReedlineMenu,
+    Signal, MenuBuilder,
     Completer, Suggestion, Span, Hinter, History,
     SearchQuery, SearchDirection, SearchFilter, CommandLineSearch,
-    CursorConfig,
+    EditCommand, HistoryItem, HistoryItemId,
 };
-use nu_ansi_term::Color;
-use std::path::PathBuf;
 use std::io::{self, Write};
+use std::time::Instant;
 use std::process::Command;
+use crate::shell::{config, correct, editmode, fc, git, histexpand, history, keybind, lexer, osstr, resolver};
 
 const NO_ARGS_CMDS: &[&str] = &["exit", "quit", "clear", "top", "htop", "pwd", "sysinfo", "version", "reload", "help", "env"];
 
-//
-// ---------------- LEVENSHTEIN ----------------
-//
-fn levenshtein(a: &str, b: &str) -> usize {
-    let a: Vec<char> = a.chars().collect();
-    let b: Vec<char> = b.chars().collect();
-    let mut dp = vec![vec![0; b.len() + 1]; a.len() + 1];
-
-    for i in 0..=a.len() { dp[i][0] = i; }
-    for j in 0..=b.len() { dp[0][j] = j; }
-
-    for i in 1..=a.len() {
-        for j in 1..=b.len() {
-            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
-            dp[i][j] = (dp[i - 1][j] + 1)
-                .min(dp[i][j - 1] + 1)
-                .min(dp[i - 1][j - 1] + cost);
-        }
-    }
-    dp[a.len()][b.len()]
-}
-
 //
 // ---------------- HINTER ----------------
 //
 struct GrshHinter {
-    all_commands: Vec<String>,
     current_hint: String,
 }
 
@@ -63,13 +39,13 @@ impl Hinter for GrshHinter {
             return String::new();
         }
 
-        let hint = get_raw_hint(line, history, &self.all_commands);
+        let hint = get_raw_hint(line, history);
         if hint.is_empty() {
             return String::new();
         }
 
         self.current_hint = hint.clone();
-        Color::Green.paint(hint).to_string()
+        config::get().colors.hint.paint(hint).to_string()
     }
 
     fn complete_hint(&self) -> String {
@@ -81,42 +57,36 @@ impl Hinter for GrshHinter {
     }
 }
 
-fn get_raw_hint(
-    line: &str,
-    history: &dyn History,
-    all_commands: &[String],
-) -> String {
-    let last_sep = line
-        .rfind(|c: char| c == ' ' || c == '|')
-        .map(|i| i + 1)
-        .unwrap_or(0);
-
-    let current_word = &line[last_sep..];
+fn get_raw_hint(line: &str, history: &dyn History) -> String {
+    let last_sep = lexer::word_start(line, line.len());
+    let current_word = lexer::unescape_word(&line[last_sep..]);
 
     if line.contains(' ') || current_word.contains('/') || current_word.starts_with('.') {
         let expanded = current_word.replacen(
             '~',
-            &dirs::home_dir().unwrap_or_default().to_string_lossy(),
+            &osstr::encode(dirs::home_dir().unwrap_or_default().as_os_str()),
             1,
         );
 
-        let path = std::path::Path::new(&expanded);
+        let expanded_os = osstr::decode(&expanded);
+        let path = std::path::Path::new(&expanded_os);
         let (dir, prefix) = if expanded.ends_with('/') {
-            (path, "")
+            (path, String::new())
         } else {
             (
                 path.parent().unwrap_or(std::path::Path::new(".")),
-                path.file_name().and_then(|n| n.to_str()).unwrap_or(""),
+                path.file_name().map(osstr::encode).unwrap_or_default(),
             )
         };
+        let prefix = prefix.as_str();
 
         let dir_to_read = if dir.as_os_str().is_empty() { std::path::Path::new(".") } else { dir };
 
         if let Ok(entries) = std::fs::read_dir(dir_to_read) {
             for entry in entries.flatten() {
-                let name = entry.file_name().to_string_lossy().to_string();
+                let name = osstr::encode(&entry.file_name());
                 if name.starts_with(prefix) && name != prefix {
-                    return name[prefix.len()..].to_string();
+                    return lexer::escape_word(&name[prefix.len()..]);
                 }
             }
         }
@@ -136,7 +106,7 @@ fn get_raw_hint(
     if let Ok(results) = history.search(query) {
         if let Some(entry) = results.first() {
             if let Some(hist_first) = entry.command_line.split_whitespace().next() {
-                if all_commands.contains(&hist_first.to_string()) && hist_first.starts_with(line) {
+                if resolver::is_known(hist_first) && hist_first.starts_with(line) {
                     return hist_first[line.len()..].to_string();
                 }
             }
@@ -149,25 +119,27 @@ fn get_raw_hint(
 //
 // ---------------- COMPLETER ----------------
 //
-struct GrshCompleter {
-    base_commands: Vec<String>,
-}
+struct GrshCompleter;
 
 impl Completer for GrshCompleter {
     fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
-        let last_sep = line[..pos]
-            .rfind(|c: char| c == ' ' || c == '|')
-            .map(|i| i + 1)
-            .unwrap_or(0);
-
-        let current = &line[last_sep..pos];
+        let last_sep = lexer::word_start(line, pos);
+        let current = lexer::unescape_word(&line[last_sep..pos]);
         let mut out = Vec::new();
 
+        let settings = &config::get().completion;
+        // `completion.case_insensitive`: confronto in minuscolo
+        let matches = |name: &str, prefix: &str| if settings.case_insensitive {
+            name.to_lowercase().starts_with(&prefix.to_lowercase())
+        } else {
+            name.starts_with(prefix)
+        };
+
         if last_sep == 0 && !current.contains('/') && !current.starts_with('.') {
-            for cmd in &self.base_commands {
-                if cmd.starts_with(current) {
+            for cmd in resolver::command_names() {
+                if matches(&cmd, &current) {
                     out.push(Suggestion {
-                        value: cmd.clone(),
+                        value: lexer::escape_word(&cmd),
                         span: Span::new(last_sep, pos),
                         append_whitespace: true,
                         ..Default::default()
@@ -179,17 +151,19 @@ impl Completer for GrshCompleter {
 
         let expanded = current.replacen(
             '~',
-            &dirs::home_dir().unwrap_or_default().to_string_lossy(),
+            &osstr::encode(dirs::home_dir().unwrap_or_default().as_os_str()),
             1,
         );
 
-        let path = std::path::Path::new(&expanded);
+        // Lavoriamo sui byte reali: i nomi non UTF-8 restano intatti
+        let expanded_os = osstr::decode(&expanded);
+        let path = std::path::Path::new(&expanded_os);
         
         let (dir, prefix) = if expanded.ends_with('/') {
-            (path, "")
+            (path, String::new())
         } else {
             let p = path.parent().unwrap_or(std::path::Path::new("."));
-            let f = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            let f = path.file_name().map(osstr::encode).unwrap_or_default();
             if p.as_os_str().is_empty() {
                 (std::path::Path::new("."), f)
             } else {
@@ -199,20 +173,17 @@ impl Completer for GrshCompleter {
 
         if let Ok(entries) = std::fs::read_dir(dir) {
            for entry in entries.flatten() {
-               let name = entry.file_name().to_string_lossy().to_string();
+               let name = osstr::encode(&entry.file_name());
 
-            if name.starts_with(prefix) {
+            // I file nascosti solo se richiesti con `.` o con `completion.hidden`
+            if matches(&name, &prefix) && (settings.hidden || !name.starts_with('.') || prefix.starts_with('.')) {
                let is_dir = entry.path().is_dir();
-               let final_name = if name.contains(' ') {
-                   format!("\"{}\"", name)
-            } else {
-        name.clone()
-        };
+               let final_name = lexer::escape_word(&name);
 
         let mut value = if dir == std::path::Path::new(".") && !current.starts_with("./") && !current.starts_with('/') {
                final_name
         } else {
-               let mut base = dir.to_string_lossy().to_string();
+               let mut base = lexer::escape_word(&osstr::encode(dir.as_os_str()));
                if !base.ends_with('/') { base.push('/'); }
                format!("{}{}", base, final_name)
         };
@@ -222,7 +193,7 @@ impl Completer for GrshCompleter {
              }
 
              out.push(Suggestion {
-                value, // Ora 'value' contiene i backslash corretti
+                value, // Nome con i caratteri speciali protetti da `\`
                 span: Span::new(last_sep, pos),
                 append_whitespace: !is_dir,
                 ..Default::default()
@@ -235,78 +206,103 @@ impl Completer for GrshCompleter {
     }
 }
 
-//
-// ---------------- REPL LOOP ----------------
-//
-pub fn repl_loop(runner: fn(String) -> bool) {
-    let mut history_path = dirs::home_dir().unwrap_or(PathBuf::from("."));
-    history_path.push(".grsh_history");
-
-    let history = Box::new(
-        FileBackedHistory::with_file(1000, history_path)
-            .expect("History error"),
-    );
-
-    let mut all_commands = vec!["exit".into(), "quit".into(), "clear".into()];
-    if let Ok(path) = std::env::var("PATH") {
-        for p in std::env::split_paths(&path) {
-            if let Ok(entries) = std::fs::read_dir(p) {
-                for e in entries.flatten() {
-                    all_commands.push(e.file_name().to_string_lossy().to_string());
-                }
-            }
+// Risposta al prompt `CORRECT>`
+#[derive(PartialEq)]
+enum Answer {
+    Yes,
+    No,
+    Edit,
+    Abort,
+}
+
+// Domanda `CORRECT>` dopo Invio, fuori dall'editor come in tcsh: legge un solo
+// tasto senza attendere Invio
+fn ask_correction(fixed: &str) -> Answer {
+    use crossterm::event::{self, Event, KeyEvent, KeyModifiers as CtKeyModifiers, KeyCode as CtKeyCode};
+    use crossterm::terminal;
+
+    print!("CORRECT>{} (y|n|e|a)? ", fixed);
+    let _ = io::stdout().flush();
+    if terminal::enable_raw_mode().is_err() { return Answer::No; }
+    let answer = loop {
+        match event::read() {
+            Ok(Event::Key(KeyEvent { code: CtKeyCode::Char('c'), modifiers, .. }))
+                if modifiers.contains(CtKeyModifiers::CONTROL) => break Answer::Abort,
+            Ok(Event::Key(KeyEvent { code: CtKeyCode::Char(c), .. })) => match c.to_ascii_lowercase() {
+                'y' => break Answer::Yes,
+                'n' => break Answer::No,
+                'e' => break Answer::Edit,
+                'a' => break Answer::Abort,
+                _ => {}
+            },
+            Ok(Event::Key(KeyEvent { code: CtKeyCode::Enter, .. })) => break Answer::No,
+            Ok(Event::Key(KeyEvent { code: CtKeyCode::Esc, .. })) => break Answer::Abort,
+            Ok(_) => {}
+            Err(_) => break Answer::No,
         }
+    };
+    let _ = terminal::disable_raw_mode();
+    let key = match answer {
+        Answer::Yes => "y",
+        Answer::No => "n",
+        Answer::Edit => "e",
+        Answer::Abort => "a",
+    };
+    println!("{}", key);
+    answer
+}
+
+// Completa la voce appena salvata in cronologia con exit status e durata
+fn record_result(editor: &mut Reedline, saved: Option<HistoryItemId>, success: bool, started: Instant) {
+    let duration = started.elapsed();
+    crate::shell::state::set_last_duration(duration);
+    let status = if success { 0 } else { crate::shell::state::last_status().max(1) as i64 };
+    let updater = |mut item: HistoryItem| {
+        item.exit_status = Some(status);
+        item.duration = Some(duration);
+        item
+    };
+    // Le righe espanse le ha salvate il REPL, le altre reedline
+    match saved {
+        Some(id) => history::update(id, &updater),
+        None => { let _ = editor.update_last_command_context(&updater); }
     }
-    let mut builtins_list = vec![
-        "setenv".into(), "set".into(), "alias".into(), 
-        "source".into(), "echo".into(), "if".into(), "endif".into(),
-        "mkcd".into(), "calc".into(), "sysinfo".into(), // <--- Nuovi
-        "reload".into(), "help".into(), "type".into(),   // <--- Nuovi
-        "jobs".into(), "fg".into(), "zap".into(), // <--- AGGIUNTI QUI
-    ];
-    all_commands.append(&mut builtins_list);
-    all_commands.sort();
-    all_commands.dedup();
-
-    let mut keybindings = reedline::default_emacs_keybindings();
-    keybindings.add_binding(
-        KeyModifiers::CONTROL,
-        KeyCode::Char('g'),
-        ReedlineEvent::HistoryHintComplete,
-    );
-    
-    keybindings.add_binding(
-        KeyModifiers::NONE,
-        KeyCode::Tab,
-        ReedlineEvent::UntilFound(vec![
-            ReedlineEvent::Menu("completion_menu".to_string()),
-            ReedlineEvent::MenuNext,
-        ]),
-    );
+}
 
+//
+// ---------------- REPL LOOP ----------------
+//
+// Editor con le impostazioni correnti di config.toml
+fn build_editor() -> Reedline {
     let menu = ColumnarMenu::default()
         .with_name("completion_menu")
-        .with_columns(4)
+        .with_columns(config::get().completion.columns)
         .with_marker("");
 
-    let highlighter = Box::new(crate::shell::syntax::GrshHighlighter {
-        commands: all_commands.clone(),
-    });
+    let highlighter = Box::new(crate::shell::syntax::GrshHighlighter);
 
-    // Inizializzazione editor con CursorConfig di default
-    let mut editor = Reedline::create()
-        .with_history(history)
-        .with_completer(Box::new(GrshCompleter {
-            base_commands: all_commands.clone(),
-        }))
+    Reedline::create()
+        .with_history(Box::new(history::SharedHistory))
+        .with_history_session_id(history::session())
+        .with_completer(Box::new(GrshCompleter))
         .with_hinter(Box::new(GrshHinter {
-            all_commands: all_commands.clone(),
             current_hint: String::new(),
         }))
         .with_highlighter(highlighter)
+        .with_validator(Box::new(crate::shell::validator::GrshValidator))
         .with_menu(ReedlineMenu::EngineCompleter(Box::new(menu)))
-        .with_edit_mode(Box::new(Emacs::new(keybindings)))
-        .with_cursor_config(CursorConfig::default());
+        .with_quick_completions(config::get().completion.quick)
+        .with_edit_mode(editmode::edit_mode())
+        .with_cursor_config(editmode::cursor_config())
+        .with_transient_prompt(Box::new(crate::config::grshrc::TransientPrompt))
+}
+
+pub fn repl_loop(runner: fn(String) -> bool) {
+    // Modalità (emacs/vi), cursori e tasti si rileggono a ogni cambio di
+    // `editmode`, `GRSH_CURSOR` o `bindkey`; tutto l'editor dopo un `reload`
+    let mut edit_settings = (editmode::mode(), editmode::preferred_cursor(), keybind::generation());
+    let mut config_generation = config::generation();
+    let mut editor = build_editor();
     // --- PATCH: IGNORA CTRL+Z NELLA SHELL PADRE ---
     // Questo evita che la shell si chiuda/sospenda se premi Ctrl+Z a vuoto sul prompt
     unsafe {
@@ -317,86 +313,122 @@ pub fn repl_loop(runner: fn(String) -> bool) {
     let prompt = crate::config::grshrc::GrshPrompt;
 
     loop {
-        match editor.read_line(&prompt) {
-            Ok(Signal::Success(line)) => {
-                let cleaned = line.trim().to_string();
-                if cleaned.is_empty() { continue; }
+        // `set autoreload`: config.toml o ~/.grshrc modificati vengono ricaricati al prompt
+        if crate::shell::state::is_set("autoreload") && config::changed() {
+            runner("reload".to_string());
+        }
+        if config::generation() != config_generation {
+            config_generation = config::generation();
+            edit_settings = (editmode::mode(), editmode::preferred_cursor(), keybind::generation());
+            editor = build_editor();
+        }
 
-                let first = cleaned.split_whitespace().next().unwrap_or("");
-
-                // Se il comando è conosciuto (sta in all_commands) lo eseguiamo subito
-                if all_commands.contains(&first.to_string()) 
-                    || first.contains('/') 
-                    || first.starts_with('?') 
-                    || first.starts_with('#') 
-                {
-                    if cleaned == "exit" || cleaned == "quit" { break; }
-                    
-                    if cleaned.starts_with('?') {
-                        let q = cleaned[1..].trim();
-                        if !q.is_empty() {
-                            let _ = Command::new("tgpt").arg("-s").arg(q).status();
-                        }
-                        continue;
-                    }
+        // Cronologia condivisa: a ogni prompt arrivano i comandi delle altre shell
+        if crate::shell::state::is_set("sharehistory") { history::pull(); }
+
+        // Ctrl+X Ctrl+E apre la riga in `$VISUAL` / `$EDITOR`, letti ogni volta
+        if let Some((buffer_editor, buffer_file)) = fc::buffer_editor() {
+            editor = editor.with_buffer_editor(buffer_editor, buffer_file);
+        }
+
+        // `set autolist` come in tcsh: Tab completa la parte comune e poi elenca
+        let partial = crate::shell::state::is_set("autolist") || config::get().completion.partial;
+        editor = editor.with_partial_completions(partial);
+
+        // `set editmode = vi`, `bindkey ...`, `set GRSH_CURSOR = 6` valgono dal prompt successivo
+        let settings = (editmode::mode(), editmode::preferred_cursor(), keybind::generation());
+        if settings != edit_settings {
+            edit_settings = settings;
+            editor = editor
+                .with_edit_mode(editmode::edit_mode())
+                .with_cursor_config(editmode::cursor_config());
+        }
 
-                    runner(cleaned); // QUI esegue i tuoi nuovi comandi!
+        crate::shell::prompt::invalidate();
+        match editor.read_line(&prompt) {
+            Ok(Signal::Success(line)) => {
+                // Tasto legato con `bindkey -c`: il comando non passa dalla cronologia
+                // e la riga in modifica resta com'era
+                if let Some(command) = keybind::take_host_command() {
+                    runner(osstr::encode_str(&command));
+                    git::invalidate();
                     continue;
                 }
 
-                // --- Se arriviamo qui, il comando è SCONOSCIUTO ---
-                // Cancelliamo l'errore dalla cronologia
-                let del_query = SearchQuery {
-                    direction: SearchDirection::Backward,
-                    start_time: None, end_time: None, start_id: None, end_id: None,
-                    filter: SearchFilter::from_text_search(CommandLineSearch::Prefix("".to_string()), None),
-                    limit: Some(1),
-                };
-                if let Ok(results) = editor.history_mut().search(del_query) {
-                    if let Some(entry) = results.first() {
-                        if let Some(id) = entry.id {
-                            let _ = editor.history_mut().delete(id);
-                            let _ = editor.history_mut().sync();
+                // Espansione `!!`, `!$`, `^old^new`: reedline non ha salvato la riga
+                // (vedi `SharedHistory`), in cronologia va quella espansa
+                let mut saved = None;
+                let line = if histexpand::has_references(&line) {
+                    match histexpand::expand(&line, &history::Events) {
+                        Ok(Some(expansion)) => {
+                            println!("{}", expansion.line);
+                            saved = history::add(&expansion.line);
+                            if expansion.print_only { continue; }
+                            expansion.line
                         }
-                    }
-                }
-
-                // Suggerimento Levenshtein (il "Forse volevi...")
-                let mut best: Option<(String, usize)> = None;
-                for cmd in &all_commands {
-                    let d = levenshtein(first, cmd);
-                    if d > 0 && d <= 2 {
-                        if best.as_ref().map_or(true, |(_, old)| d < *old) {
-                            best = Some((cmd.clone(), d));
+                        Ok(None) => {
+                            saved = history::add(&line);
+                            line
+                        }
+                        Err(msg) => {
+                            eprintln!("{}", msg);
+                            crate::shell::state::set_exit_status(false);
+                            continue;
                         }
                     }
-                }
+                } else {
+                    line
+                };
+                let mut cleaned = line.trim().to_string();
+                if cleaned.is_empty() { continue; }
 
-                if let Some((corr, _)) = best {
-                    print!("grsh: comando '{}' non trovato. Forse volevi '{}'? [y/N]: ", first, corr);
-                    std::io::stdout().flush().ok();
-                    let mut resp = String::new();
-                    std::io::stdin().read_line(&mut resp).ok();
-                    if resp.trim().eq_ignore_ascii_case("y") {
-                        runner(cleaned.replacen(first, &corr, 1));
+                // Correzione ortografica (`set correct = cmd|all`)
+                if !cleaned.starts_with('?') {
+                    if let Some(fixed) = correct::correct_line(&cleaned, correct::mode()) {
+                        match ask_correction(&fixed) {
+                            Answer::Yes => {
+                                // In cronologia va la riga effettivamente eseguita
+                                history::replace_corrected(&cleaned, &fixed);
+                                cleaned = fixed;
+                            }
+                            Answer::No => {}
+                            Answer::Edit => {
+                                // La riga corretta torna nell'editor per essere ritoccata
+                                editor.run_edit_commands(&[EditCommand::InsertString(fixed)]);
+                                continue;
+                            }
+                            Answer::Abort => continue,
+                        }
                     }
-                } else {
-                    println!("grsh: comando '{}' non trovato.", first);
                 }
 
-                if cleaned == "exit" || cleaned == "quit" {
-                    break;
-                }
+                if cleaned == "exit" || cleaned == "quit" { break; }
 
-                if cleaned.starts_with('?') {
-                    let q = cleaned[1..].trim();
+                // `? domanda` va all'assistente di `[ai]` (tgpt -s se non configurato)
+                if let Some(q) = cleaned.strip_prefix('?').filter(|_| config::get().ai.enabled) {
+                    let q = q.trim();
                     if !q.is_empty() {
-                        let _ = Command::new("tgpt").arg("-s").arg(q).status();
+                        let ai = &config::get().ai;
+                        if let Err(e) = Command::new(&ai.command).args(&ai.args).arg(q).status() {
+                            eprintln!("grsh: {}: {}", ai.command, e);
+                        }
                     }
                     continue;
                 }
 
-                runner(cleaned);
+                let started = Instant::now();
+                let success = runner(osstr::encode_str(&cleaned)); // QUI esegue i tuoi nuovi comandi!
+                record_result(&mut editor, saved, success, started);
+                git::invalidate();
+
+                // Comando davvero inesistente: suggeriamo il più simile (il "Forse volevi...")
+                if !success {
+                    if let Some(first) = resolver::command_word(&cleaned).filter(|c| !resolver::is_known(c)) {
+                        if let Some(corr) = correct::closest_command(&first) {
+                            println!("grsh: forse volevi '{}'?", corr);
+                        }
+                    }
+                }
             }
 
             Ok(Signal::CtrlC) => println!("^C"),
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use lazy_static::lazy_static;
+use crate::shell::{alias, builtins, glob, lexer, osstr};
+
+// --- RISOLUZIONE DEI COMANDI ---
+// Unica fonte di verità per REPL, evidenziazione e completamento: alias e builtin
+// vengono letti al momento, gli eseguibili del PATH stanno in una tabella che si
+// ricostruisce da sola quando cambia `$PATH` o una delle sue directory (mtime).
+
+/// Cosa eseguirebbe una parola in posizione di comando
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    Alias(String),
+    Builtin,
+    /// Eseguibile trovato nel PATH
+    Path(PathBuf),
+    /// Percorso esplicito (`./script`, `/usr/bin/env`)
+    File(PathBuf),
+    Unknown,
+}
+
+#[derive(Default)]
+struct PathHash {
+    path_var: Option<OsString>,
+    dirs: Vec<(PathBuf, Option<SystemTime>)>,
+    commands: HashMap<String, PathBuf>,
+}
+
+lazy_static! {
+    static ref HASH: Mutex<PathHash> = Mutex::new(PathHash::default());
+}
+
+fn mtime(dir: &Path) -> Option<SystemTime> {
+    fs::metadata(dir).and_then(|m| m.modified()).ok()
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+impl PathHash {
+    fn is_stale(&self) -> bool {
+        self.path_var != env::var_os("PATH")
+            || self.dirs.iter().any(|(dir, time)| mtime(dir) != *time)
+    }
+
+    fn rebuild(&mut self) {
+        self.path_var = env::var_os("PATH");
+        self.dirs.clear();
+        self.commands.clear();
+        let Some(path_var) = self.path_var.clone() else { return; };
+        for dir in env::split_paths(&path_var) {
+            self.dirs.push((dir.clone(), mtime(&dir)));
+            let Ok(entries) = fs::read_dir(&dir) else { continue; };
+            for entry in entries.flatten() {
+                let name = osstr::encode(&entry.file_name());
+                // Vince la prima directory del PATH, come nell'esecuzione
+                if self.commands.contains_key(&name) { continue; }
+                let path = entry.path();
+                if is_executable(&path) { self.commands.insert(name, path); }
+            }
+        }
+    }
+}
+
+fn with_hash<T>(f: impl FnOnce(&PathHash) -> T) -> T {
+    let mut hash = HASH.lock().unwrap();
+    if hash.is_stale() { hash.rebuild(); }
+    f(&hash)
+}
+
+/// Ricostruisce subito la tabella degli eseguibili (builtin `rehash`)
+pub fn rehash() {
+    HASH.lock().unwrap().rebuild();
+}
+
+pub fn resolve(name: &str) -> Resolution {
+    if name.is_empty() { return Resolution::Unknown; }
+    if name.contains('/') {
+        let path = PathBuf::from(osstr::decode(name));
+        return if is_executable(&path) { Resolution::File(path) } else { Resolution::Unknown };
+    }
+    if let Some(value) = alias::get_alias(name) { return Resolution::Alias(value); }
+    if builtins::is_builtin(name) { return Resolution::Builtin; }
+    match lookup(name) {
+        Some(path) => Resolution::Path(path),
+        None => Resolution::Unknown,
+    }
+}
+
+fn lookup(name: &str) -> Option<PathBuf> {
+    let mut hash = HASH.lock().unwrap();
+    if hash.is_stale() { hash.rebuild(); }
+    if let Some(path) = hash.commands.get(name) { return Some(path.clone()); }
+
+    // Un `chmod +x` non cambia l'mtime della directory: riproviamo sul disco
+    let path_var = hash.path_var.clone()?;
+    let found = env::split_paths(&path_var)
+        .map(|dir| dir.join(osstr::decode(name)))
+        .find(|path| is_executable(path))?;
+    hash.commands.insert(name.to_string(), found.clone());
+    Some(found)
+}
+
+pub fn is_known(name: &str) -> bool {
+    resolve(name) != Resolution::Unknown
+}
+
+/// Nomi di tutti i comandi disponibili (per completamento e correzione)
+pub fn command_names() -> Vec<String> {
+    let mut names: Vec<String> = with_hash(|h| h.commands.keys().cloned().collect());
+    names.extend(alias::get_all_aliases().into_iter().map(|(n, _)| n));
+    names.extend(builtins::names().map(String::from));
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Vero per le assegnazioni `NOME=valore` (parola in forma di pattern del lexer)
+pub fn is_assignment(word: &str) -> bool {
+    let Some((name, _)) = word.split_once('=') else { return false; };
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parola in posizione di comando, saltando le assegnazioni `VAR=x` iniziali
+pub fn command_word(line: &str) -> Option<String> {
+    lexer::smart_split(line)
+        .into_iter()
+        .find(|w| !is_assignment(w))
+        .map(|w| glob::unescape(&w))
+}
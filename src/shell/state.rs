@@ -1,8 +1,12 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicI32, Ordering};
 use std::sync::{Arc, Mutex};
 use lazy_static::lazy_static;
 use std::env;
+use std::ffi::{OsStr, OsString};
 use std::path::PathBuf;
+use std::time::Duration;
+use crate::shell::osstr;
 
 // --- STRUTTURA PER LA JOB TABLE ---
 #[derive(Clone, Debug)]
@@ -19,6 +23,12 @@ lazy_static! {
     
     // La Job Table per gestire i processi sospesi (Ctrl+Z)
     static ref JOBS: Mutex<Vec<Job>> = Mutex::new(Vec::new());
+
+    // Durata dell'ultimo comando eseguito dal REPL
+    static ref LAST_DURATION: Mutex<Option<Duration>> = Mutex::new(None);
+
+    // Ambiente ricevuto all'avvio, prima dei file di configurazione
+    static ref INITIAL_ENV: HashMap<OsString, OsString> = env::vars_os().collect();
 }
 
 // --- GESTIONE JOB (STILE BASH) ---
@@ -81,45 +91,148 @@ pub fn get_var(key: &str) -> Option<String> {
 pub fn set_var(key: &str, value: &str) {
     let mut vars = ENV_VARS.lock().unwrap();
     vars.insert(key.to_string(), value.to_string());
-    env::set_var(key, value);
+    env::set_var(key, osstr::decode(value));
 }
 
-pub fn set_exit_status(success: bool) {
-    let status = if success { "0" } else { "1" };
-    set_var("?", status);
+/// Variabile solo della shell, non esportata ai processi figli
+/// (opzioni come `set nullglob`)
+pub fn set_shell_var(key: &str, value: &str) {
+    ENV_VARS.lock().unwrap().insert(key.to_string(), value.to_string());
 }
 
-pub fn expand_env_vars(line: &str) -> String {
-    let mut expanded = line.to_string();
+pub fn unset_var(key: &str) {
+    ENV_VARS.lock().unwrap().remove(key);
+    env::remove_var(key);
+}
 
-    {
-        let vars = ENV_VARS.lock().unwrap();
-        for (key, value) in vars.iter() {
-            let target = format!("${}", key);
-            expanded = expanded.replace(&target, value);
-        }
-    }
+/// Fotografa l'ambiente di partenza: da chiamare prima di leggere la configurazione
+pub fn remember_environment() {
+    lazy_static::initialize(&INITIAL_ENV);
+}
 
-    for (key, value) in env::vars() {
-        let target = format!("${}", key);
-        if expanded.contains(&target) {
-            expanded = expanded.replace(&target, &value);
-        }
+/// Dimentica le variabili e le opzioni di shell (per `reload`): quelle ereditate
+/// tornano al valore di partenza (anche se tolte con `unsetenv`), le altre
+/// spariscono anche dall'ambiente. PWD e OLDPWD seguono la directory corrente
+/// e restano come sono.
+pub fn reset_vars() {
+    let keep = |key: &OsStr| key != "PWD" && key != "OLDPWD";
+    let vars: Vec<String> = ENV_VARS.lock().unwrap().drain().map(|(key, _)| key).collect();
+    for key in vars.iter().filter(|k| keep(OsStr::new(k))) {
+        if !INITIAL_ENV.contains_key(OsStr::new(key)) { env::remove_var(key); }
     }
+    for (key, value) in INITIAL_ENV.iter().filter(|(k, _)| keep(k)) {
+        if env::var_os(key).as_ref() != Some(value) { env::set_var(key, value); }
+    }
+}
 
-    let home = env::var("HOME").unwrap_or_else(|_| "/root".to_string());
-    
-    let words: Vec<String> = expanded.split(' ').map(|word| {
-        if word == "~" {
-            home.clone()
-        } else if word.starts_with("~/") {
-            word.replacen('~', &home, 1)
-        } else {
-            word.to_string()
-        }
-    }).collect();
+/// Vero se la variabile di shell esiste (opzioni booleane tipo `set nullglob`)
+pub fn is_set(key: &str) -> bool {
+    ENV_VARS.lock().unwrap().contains_key(key)
+}
+
+// Codice d'uscita dell'ultimo processo esterno, raccolto da `set_exit_status`
+static CHILD_STATUS: AtomicI32 = AtomicI32::new(0);
+
+/// Registra il codice d'uscita di un processo (128 + segnale se terminato da un segnale)
+pub fn set_child_status(code: i32) {
+    CHILD_STATUS.store(code, Ordering::Relaxed);
+}
 
-    words.join(" ")
+/// Stato dell'ultimo comando in `$?` e `$status`: il codice del processo se è
+/// fallito, altrimenti 0 o 1
+pub fn set_exit_status(success: bool) {
+    let child = CHILD_STATUS.swap(0, Ordering::Relaxed);
+    let code = match (success, child) {
+        (true, _) => 0,
+        (false, 0) => 1,
+        (false, code) => code,
+    };
+    set_shell_var("?", &code.to_string());
+    set_shell_var("status", &code.to_string());
+}
+
+pub fn last_status() -> i32 {
+    get_var("?").and_then(|s| s.parse().ok()).unwrap_or(0)
+}
+
+/// Durata dell'ultimo comando, anche in millisecondi in `$CMD_DURATION`
+pub fn set_last_duration(duration: Duration) {
+    *LAST_DURATION.lock().unwrap() = Some(duration);
+    set_shell_var("CMD_DURATION", &duration.as_millis().to_string());
+}
+
+pub fn last_duration() -> Option<Duration> {
+    *LAST_DURATION.lock().unwrap()
+}
+
+/// Sostituisce `$VAR`, `${VAR}` e `$?` fuori dagli apici singoli.
+/// `\$` e il contenuto di `'...'` / `$'...'` restano letterali (li gestisce il lexer).
+pub fn expand_env_vars(line: &str) -> String {
+    let mut expanded = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    let (mut single, mut double, mut ansi) = (false, false, false);
+
+    while let Some(c) = chars.next() {
+        expanded.push(c);
+        if single {
+            if c == '\'' { single = false; }
+            continue;
+        }
+        if ansi {
+            match c {
+                '\\' => if let Some(n) = chars.next() { expanded.push(n); },
+                '\'' => ansi = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '\\' => if let Some(n) = chars.next() { expanded.push(n); },
+            '\'' if !double => single = true,
+            '"' => double = !double,
+            '$' => {
+                if !double && chars.peek() == Some(&'\'') {
+                    expanded.push('\'');
+                    chars.next();
+                    ansi = true;
+                    continue;
+                }
+
+                let braced = chars.peek() == Some(&'{');
+                let mut name = String::new();
+                if braced {
+                    let mut probe = chars.clone();
+                    probe.next();
+                    let inner: String = probe.by_ref().take_while(|&n| n != '}').collect();
+                    if !inner.is_empty() && inner.chars().all(|n| n.is_alphanumeric() || n == '_') {
+                        name = inner;
+                        chars = probe;
+                    }
+                } else if chars.peek() == Some(&'?') {
+                    chars.next();
+                    name.push('?');
+                } else {
+                    while let Some(&n) = chars.peek() {
+                        if !(n.is_alphanumeric() || n == '_') { break; }
+                        name.push(n);
+                        chars.next();
+                    }
+                }
+                if name.is_empty() { continue; }
+
+                match get_var(&name).or_else(|| env::var_os(&name).map(|v| osstr::encode(&v))) {
+                    Some(value) => {
+                        expanded.pop();
+                        expanded.push_str(&value);
+                    }
+                    None if braced => expanded.push_str(&format!("{{{}}}", name)),
+                    None => expanded.push_str(&name),
+                }
+            }
+            _ => {}
+        }
+    }
+    expanded
 }
 
 pub fn find_in_path(cmd: &str) -> Option<PathBuf> {
@@ -1,36 +1,60 @@
 use reedline::{Highlighter, StyledText};
-use nu_ansi_term::{Color, Style};
+use nu_ansi_term::Style;
 use std::path::Path;
+use crate::shell::{config, resolver};
 
-pub struct GrshHighlighter {
-    pub commands: Vec<String>,
-}
+pub struct GrshHighlighter;
 
 impl Highlighter for GrshHighlighter {
     fn highlight(&self, line: &str, _pos: usize) -> StyledText {
         let mut styled_text = StyledText::new();
         if line.is_empty() { return styled_text; }
+        // Colori da `[colors]` di config.toml
+        let colors = &config::get().colors;
 
         let words = line.split_inclusive(' ');
+        // Vero finché non incontriamo la parola in posizione di comando
+        let mut expect_command = true;
 
-        for (i, word) in words.enumerate() {
+        for word in words {
             let trimmed = word.trim_end();
             let space_suffix = &word[trimmed.len()..];
 
+            if trimmed.is_empty() {
+                styled_text.push((Style::new(), word.to_string()));
+                continue;
+            }
+
+            // Dopo `|`, `&&` e `;` ricomincia un comando
+            if matches!(trimmed, "|" | "|&" | "&&" | "||" | ";") {
+                styled_text.push((colors.operator, trimmed.to_string()));
+                styled_text.push((Style::new(), space_suffix.to_string()));
+                expect_command = true;
+                continue;
+            }
+
+            // Assegnazioni `VAR=x` prima del comando
+            if expect_command && resolver::is_assignment(trimmed) {
+                styled_text.push((colors.assignment, trimmed.to_string()));
+                styled_text.push((Style::new(), space_suffix.to_string()));
+                continue;
+            }
+
             // 1. COMANDO PRINCIPALE
-            if i == 0 {
-                let exists = self.commands.contains(&trimmed.to_string()) 
-                             || trimmed.contains('/') 
-                             || trimmed.starts_with('.');
-                let style = if exists { Color::Cyan } else { Color::Red };
-                styled_text.push((Style::new().fg(style).bold(), trimmed.to_string()));
+            if expect_command {
+                expect_command = false;
+                let exists = resolver::is_known(&crate::shell::lexer::unescape_word(trimmed))
+                             || trimmed.starts_with('?')
+                             || trimmed.starts_with('#');
+                let style = if exists { colors.command } else { colors.unknown_command };
+                styled_text.push((style, trimmed.to_string()));
                 styled_text.push((Style::new(), space_suffix.to_string()));
                 continue;
             }
 
             // 2. FLAG
             if trimmed.starts_with('-') {
-                styled_text.push((Style::new().fg(Color::Yellow), trimmed.to_string()));
+                styled_text.push((colors.flag, trimmed.to_string()));
                 styled_text.push((Style::new(), space_suffix.to_string()));
                 continue;
             }
@@ -42,25 +66,25 @@ impl Highlighter for GrshHighlighter {
                 if path.exists() {
                     if path.is_dir() {
                         // È tutto una cartella
-                        styled_text.push((Style::new().fg(Color::Blue).bold(), trimmed.to_string()));
+                        styled_text.push((colors.directory, trimmed.to_string()));
                     } else {
                         // È un file (o un link a un file): separiamo l'ultima parte
                         if let Some(slash_pos) = trimmed.rfind('/') {
                             let folder_part = &trimmed[..=slash_pos];
                             let file_part = &trimmed[slash_pos + 1..];
-                            styled_text.push((Style::new().fg(Color::Blue).bold(), folder_part.to_string()));
-                            styled_text.push((Style::new().fg(Color::White), file_part.to_string()));
+                            styled_text.push((colors.directory, folder_part.to_string()));
+                            styled_text.push((colors.file, file_part.to_string()));
                         } else {
-                            styled_text.push((Style::new().fg(Color::White), trimmed.to_string()));
+                            styled_text.push((colors.file, trimmed.to_string()));
                         }
                     }
                 } else {
                     // Il percorso non esiste ancora (mentre scrivi) o è testo
-                    styled_text.push((Style::new().fg(Color::Fixed(250)), trimmed.to_string()));
+                    styled_text.push((colors.missing_path, trimmed.to_string()));
                 }
             } else {
                 // Testo normale
-                styled_text.push((Style::new().fg(Color::White), trimmed.to_string()));
+                styled_text.push((colors.text, trimmed.to_string()));
             }
 
             // Aggiungi lo spazio finale
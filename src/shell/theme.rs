@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+use lazy_static::lazy_static;
+use nu_ansi_term::{Color, Style};
+use serde::Deserialize;
+use toml::{Table, Value};
+use crate::shell::{git, prompt, state, toolchain};
+
+// --- TEMI DEL PROMPT: SEZIONE `[prompt]` DI config.toml (vedi `shell/config.rs`) ---
+//   [prompt]
+//   theme = "minimal"                  # default, minimal, plain, full
+//   left = ["cwd", "git"]              # ordine dei segmenti a sinistra
+//   right = ["duration", "time"]       # e a destra
+//   separator = " "                    # tra due segmenti
+//   cwd_length = 3                     # ultime N directory (0 = tutto il percorso)
+//   duration_threshold = 5000          # `duration` solo oltre 5 secondi (in millisecondi)
+//   indicator = " ❯ "
+//   indicator_style = "purple bold"
+//
+//   [prompt.git]
+//   style = "208 bold"                 # colore (nome, 0-255, #rrggbb), bg:colore, bold...
+//   format = "({})"                    # `{}` è il valore del segmento
+//   separator = ""                     # al posto di quello globale, prima del segmento
+//   disabled = true                    # nasconde il segmento anche se è in `left`/`right`
+//
+// Il tema scelto fa da base e le chiavi scritte in `[prompt]` lo sovrascrivono.
+// I segmenti virtualenv, rust, node, tools e kube vengono da `shell/toolchain.rs`.
+// Un segmento senza valore (nessun job, stato 0, fuori da un repository) sparisce
+// insieme al suo separatore. `set prompt` e `set rprompt` hanno la precedenza.
+
+/// Segmenti disponibili
+pub const SEGMENTS: &[&str] = &[
+    "user", "host", "cwd", "git", "jobs", "status", "duration", "time",
+    "virtualenv", "rust", "node", "tools", "kube",
+];
+
+// Riproduce il prompt storico: `[1] utente@host [main] ~/src ➜ `
+const DEFAULT: &str = r#"
+left = ["jobs", "user", "host", "git", "cwd"]
+right = []
+separator = " "
+cwd_length = 0
+duration_threshold = 2000
+indicator = " ➜ "
+indicator_style = ""
+jobs = { style = "yellow bold", format = "[{}]" }
+user = { style = "green bold", root_style = "red bold" }
+host = { style = "green", format = "@{}", separator = "" }
+git = { style = "208 bold", format = "[{}]" }
+cwd = { style = "blue bold" }
+status = { style = "red bold", format = "✘ {}" }
+duration = { style = "yellow" }
+time = { style = "darkgray" }
+virtualenv = { style = "cyan", format = "({})" }
+rust = { style = "red", format = "rust {}" }
+node = { style = "green", format = "node {}" }
+tools = { style = "dimmed" }
+kube = { style = "blue", format = "⎈ {}" }
+"#;
+
+const MINIMAL: &str = r#"
+left = ["virtualenv", "cwd", "git"]
+cwd_length = 3
+indicator = " ❯ "
+indicator_style = "purple bold"
+cwd = { style = "cyan bold" }
+git = { style = "purple", format = "{}" }
+virtualenv = { style = "dimmed", format = "{}" }
+"#;
+
+const PLAIN: &str = r#"
+left = ["user", "host", "cwd"]
+indicator = "$ "
+user = { style = "", root_style = "" }
+host = { style = "", format = "@{}" }
+cwd = { style = "", separator = ":" }
+"#;
+
+const FULL: &str = r#"
+left = ["status", "jobs", "virtualenv", "user", "host", "cwd", "git"]
+right = ["rust", "node", "tools", "kube", "duration", "time"]
+cwd_length = 4
+"#;
+
+/// Temi inclusi, applicati sopra `default`
+pub const THEMES: &[(&str, &str)] = &[
+    ("default", DEFAULT),
+    ("minimal", MINIMAL),
+    ("plain", PLAIN),
+    ("full", FULL),
+];
+
+// Chiavi di `[prompt]` oltre alle tabelle dei segmenti
+const OPTIONS: &[&str] = &[
+    "left", "right", "separator", "cwd_length", "duration_threshold", "indicator", "indicator_style",
+];
+
+/// Forma della sezione `[prompt]` dopo l'unione con il tema
+#[derive(Debug, Deserialize)]
+struct ThemeConfig {
+    left: Vec<String>,
+    right: Vec<String>,
+    separator: String,
+    cwd_length: usize,
+    duration_threshold: u64,
+    indicator: String,
+    indicator_style: String,
+    // Letti uno per uno, per dire in quale segmento è l'errore
+    #[serde(flatten)]
+    segments: HashMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SegmentConfig {
+    style: Option<String>,
+    root_style: Option<String>,
+    format: Option<String>,
+    separator: Option<String>,
+    #[serde(default)]
+    disabled: bool,
+}
+
+struct Segment {
+    style: Style,
+    root_style: Option<Style>,
+    format: String,
+    separator: Option<String>,
+    disabled: bool,
+}
+
+pub struct Theme {
+    left: Vec<String>,
+    right: Vec<String>,
+    separator: String,
+    cwd_length: usize,
+    duration_threshold: Duration,
+    indicator: String,
+    indicator_style: Style,
+    segments: HashMap<String, Segment>,
+}
+
+lazy_static! {
+    static ref THEME: RwLock<Theme> = RwLock::new(build(None).unwrap_or_else(|_| fallback()));
+}
+
+fn color(name: &str) -> Option<Color> {
+    if let Ok(n) = name.parse::<u8>() {
+        return Some(Color::Fixed(n));
+    }
+    if let Some(hex) = name.strip_prefix('#').filter(|h| h.len() == 6) {
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+    Some(match name.to_lowercase().replace(['-', '_'], "").as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "purple" | "magenta" => Color::Purple,
+        "cyan" => Color::Cyan,
+        "white" => Color::White,
+        "gray" | "grey" | "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightgray" | "lightgrey" => Color::LightGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightpurple" | "lightmagenta" => Color::LightPurple,
+        "lightcyan" => Color::LightCyan,
+        _ => return None,
+    })
+}
+
+/// `"208 bold"`, `"bg:blue white"`, `"#ff8800 underline"`; `""` non colora
+pub fn parse_style(text: &str) -> Result<Style, String> {
+    let mut style = Style::new();
+    for word in text.split_whitespace() {
+        style = match word {
+            "bold" => style.bold(),
+            "dimmed" | "dim" => style.dimmed(),
+            "italic" => style.italic(),
+            "underline" => style.underline(),
+            "blink" => style.blink(),
+            "reverse" => style.reverse(),
+            _ => match word.strip_prefix("bg:") {
+                Some(bg) => style.on(color(bg).ok_or_else(|| format!("colore non valido: {}", bg))?),
+                None => style.fg(color(word).ok_or_else(|| format!("colore non valido: {}", word))?),
+            },
+        };
+    }
+    Ok(style)
+}
+
+// Le tabelle si uniscono chiave per chiave, gli altri valori vengono sostituiti
+fn merge(base: &mut Table, over: Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(b)), Value::Table(o)) => merge(b, o),
+            (_, value) => { base.insert(key, value); }
+        }
+    }
+}
+
+fn theme_table(name: &str) -> Result<Table, String> {
+    let text = THEMES.iter().find(|(n, _)| *n == name).map(|(_, t)| *t).ok_or_else(|| {
+        let names: Vec<&str> = THEMES.iter().map(|(n, _)| *n).collect();
+        format!("tema sconosciuto: {} (disponibili: {})", name, names.join(", "))
+    })?;
+    let mut table: Table = DEFAULT.parse().map_err(|e| format!("tema default: {}", e))?;
+    merge(&mut table, text.parse().map_err(|e| format!("tema {}: {}", name, e))?);
+    Ok(table)
+}
+
+// Tema dalla sezione `[prompt]` (già letta dal file); `None` = tema predefinito
+fn build(section: Option<Table>) -> Result<Theme, String> {
+    let mut section = section.unwrap_or_default();
+    let name = match section.remove("theme") {
+        Some(Value::String(name)) => name,
+        Some(other) => return Err(format!("theme deve essere una stringa, non {}", other.type_str())),
+        None => "default".to_string(),
+    };
+    let mut table = theme_table(&name)?;
+    merge(&mut table, section);
+    if let Some(key) = table.keys().find(|k| !OPTIONS.contains(&k.as_str()) && !SEGMENTS.contains(&k.as_str())) {
+        return Err(format!("chiave sconosciuta: {}", key));
+    }
+
+    let config: ThemeConfig = Value::Table(table).try_into().map_err(|e: toml::de::Error| e.message().to_string())?;
+    for name in config.left.iter().chain(&config.right) {
+        if !SEGMENTS.contains(&name.as_str()) {
+            return Err(format!("segmento sconosciuto: {} (disponibili: {})", name, SEGMENTS.join(", ")));
+        }
+    }
+
+    let mut segments = HashMap::new();
+    for (name, seg) in config.segments {
+        let context = |e: String| format!("{}: {}", name, e);
+        let seg: SegmentConfig = seg.try_into().map_err(|e: toml::de::Error| context(e.message().to_string()))?;
+        segments.insert(name.clone(), Segment {
+            style: parse_style(seg.style.as_deref().unwrap_or("")).map_err(context)?,
+            root_style: seg.root_style.as_deref().map(parse_style).transpose().map_err(context)?,
+            format: seg.format.unwrap_or_else(|| "{}".to_string()),
+            separator: seg.separator,
+            disabled: seg.disabled,
+        });
+    }
+    Ok(Theme {
+        left: config.left,
+        right: config.right,
+        separator: config.separator,
+        cwd_length: config.cwd_length,
+        duration_threshold: Duration::from_millis(config.duration_threshold),
+        indicator: config.indicator,
+        indicator_style: parse_style(&config.indicator_style)?,
+        segments,
+    })
+}
+
+// Ultima risorsa se anche il tema predefinito non si costruisce
+fn fallback() -> Theme {
+    Theme {
+        left: vec!["cwd".to_string()],
+        right: Vec::new(),
+        separator: " ".to_string(),
+        cwd_length: 0,
+        duration_threshold: Duration::from_secs(2),
+        indicator: " ➜ ".to_string(),
+        indicator_style: Style::new(),
+        segments: HashMap::new(),
+    }
+}
+
+/// Applica la sezione `[prompt]` di config.toml (`None` = tema predefinito).
+/// Se non è valida resta il tema predefinito e si restituisce l'errore.
+pub fn apply(section: Option<Table>) -> Result<(), String> {
+    let result = build(section);
+    let error = result.as_ref().err().map(|e| format!("[prompt]: {}", e));
+    *THEME.write().unwrap() = result.or_else(|_| build(None)).unwrap_or_else(|_| fallback());
+    error.map_or(Ok(()), Err)
+}
+
+// `~/src/grsh/shell` con `cwd_length = 2` diventa `…/grsh/shell`
+fn truncated_dir(length: usize) -> String {
+    let dir = prompt::tilde_dir();
+    let parts: Vec<&str> = dir.split('/').filter(|p| !p.is_empty()).collect();
+    if length == 0 || parts.len() <= length {
+        return dir;
+    }
+    format!("…/{}", parts[parts.len() - length..].join("/"))
+}
+
+impl Theme {
+    // Valore del segmento; `None` lo nasconde
+    fn value(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "user" => prompt::user(),
+            "host" => prompt::host(false),
+            "cwd" => truncated_dir(self.cwd_length),
+            "git" => git::info()?.summary(),
+            "jobs" => Some(state::get_jobs().len()).filter(|n| *n > 0)?.to_string(),
+            "status" => prompt::failed_status()?,
+            "duration" => prompt::format_duration(state::last_duration().filter(|d| *d >= self.duration_threshold)?),
+            "time" => prompt::time("%H:%M:%S"),
+            _ => toolchain::segment(name)?,
+        };
+        Some(value)
+    }
+
+    fn render(&self, names: &[String]) -> String {
+        let mut out = String::new();
+        for name in names {
+            let segment = self.segments.get(name);
+            if segment.is_some_and(|s| s.disabled) { continue; }
+            let Some(value) = self.value(name) else { continue };
+            if !out.is_empty() {
+                out.push_str(segment.and_then(|s| s.separator.as_deref()).unwrap_or(&self.separator));
+            }
+            let text = segment.map_or("{}", |s| s.format.as_str()).replace("{}", &value);
+            let style = match segment {
+                Some(s) if name == "user" && nix::unistd::geteuid().is_root() => s.root_style.unwrap_or(s.style),
+                Some(s) => s.style,
+                None => Style::new(),
+            };
+            out.push_str(&style.paint(text).to_string());
+        }
+        out
+    }
+}
+
+/// Prompt a sinistra secondo il tema
+pub fn left() -> String {
+    let theme = THEME.read().unwrap();
+    theme.render(&theme.left)
+}
+
+/// Prompt a destra secondo il tema (vuoto se `right` non ha segmenti)
+pub fn right() -> String {
+    let theme = THEME.read().unwrap();
+    theme.render(&theme.right)
+}
+
+/// Indicatore dopo il prompt a sinistra, già colorato
+pub fn indicator() -> String {
+    let theme = THEME.read().unwrap();
+    theme.indicator_style.paint(theme.indicator.as_str()).to_string()
+}
+
+/// Sotto questa durata l'ultimo comando non compare nel prompt
+pub fn duration_threshold() -> Duration {
+    THEME.read().unwrap().duration_threshold
+}
+
+/// Il tema lascia uno spazio prima dell'indicatore
+pub fn indicator_spaced() -> bool {
+    THEME.read().unwrap().indicator.starts_with(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn theme(toml: &str) -> Result<Theme, String> {
+        build(Some(toml.parse().unwrap()))
+    }
+
+    fn error(toml: &str) -> String {
+        theme(toml).err().unwrap_or_else(|| panic!("nessun errore per: {}", toml))
+    }
+
+    #[test]
+    fn builtin_theme_over_default() {
+        let t = theme(r#"theme = "minimal""#).unwrap();
+        assert_eq!(t.left, ["virtualenv", "cwd", "git"]);
+        assert!(t.right.is_empty());
+        assert_eq!((t.cwd_length, t.indicator.as_str()), (3, " ❯ "));
+        assert_eq!(t.indicator_style, Color::Purple.bold());
+        assert_eq!(t.segments["cwd"].style, Color::Cyan.bold());
+        // Le chiavi che `minimal` non tocca restano quelle di `default`
+        assert_eq!(t.separator, " ");
+        assert_eq!(t.segments["jobs"].format, "[{}]");
+        assert_eq!(t.segments["host"].separator.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn user_keys_over_builtin_theme() {
+        let t = theme(r#"
+            theme = "minimal"
+            right = ["time"]
+            cwd_length = 0
+            git = { format = "<{}>" }
+            time = { disabled = true }
+        "#).unwrap();
+        assert_eq!(t.left, ["virtualenv", "cwd", "git"]);
+        assert_eq!(t.right, ["time"]);
+        assert_eq!(t.cwd_length, 0);
+        // Dentro un segmento si sostituiscono solo le chiavi scritte
+        assert_eq!(t.segments["git"].format, "<{}>");
+        assert_eq!(t.segments["git"].style, Color::Purple.normal());
+        assert!(t.segments["time"].disabled);
+        assert_eq!(t.segments["time"].style, Color::DarkGray.normal());
+    }
+
+    #[test]
+    fn unknown_keys_and_segments() {
+        assert_eq!(error("colour = \"red\""), "chiave sconosciuta: colour");
+        assert_eq!(error("battery = { style = \"red\" }"), "chiave sconosciuta: battery");
+        assert_eq!(
+            error(r#"left = ["cwd", "battery"]"#),
+            format!("segmento sconosciuto: battery (disponibili: {})", SEGMENTS.join(", ")),
+        );
+        assert_eq!(
+            error("git = { colour = \"red\" }"),
+            "git: unknown field `colour`, expected one of `style`, `root_style`, `format`, `separator`, `disabled`",
+        );
+        assert_eq!(error(r#"theme = "neon""#), "tema sconosciuto: neon (disponibili: default, minimal, plain, full)");
+        assert_eq!(error("theme = 1"), "theme deve essere una stringa, non integer");
+        assert_eq!(error(r#"git = { style = "rosa" }"#), "git: colore non valido: rosa");
+    }
+
+    #[test]
+    fn invalid_section_keeps_the_default_theme() {
+        let section: Table = r#"left = ["nope"]"#.parse().unwrap();
+        assert_eq!(apply(Some(section)).unwrap_err(), format!("[prompt]: segmento sconosciuto: nope (disponibili: {})", SEGMENTS.join(", ")));
+        assert_eq!(THEME.read().unwrap().left, ["jobs", "user", "host", "git", "cwd"]);
+        assert!(apply(None).is_ok());
+    }
+}
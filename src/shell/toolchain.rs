@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use lazy_static::lazy_static;
+use regex::Regex;
+use toml::Table;
+
+// --- SEGMENTI DI CONTESTO: AMBIENTE PYTHON, TOOLCHAIN RUST E NODE, ASDF, KUBERNETES ---
+// Si leggono solo variabili d'ambiente e file locali, mai processi (`rustc -V`,
+// `node -v`, `kubectl` sono troppo lenti per un prompt):
+//   virtualenv  $VIRTUAL_ENV (o $VIRTUAL_ENV_PROMPT), altrimenti $CONDA_DEFAULT_ENV
+//   rust        $RUSTUP_TOOLCHAIN, rust-toolchain.toml o rust-toolchain risalendo le directory
+//   node        .nvmrc, altrimenti `engines.node` del package.json più vicino
+//   tools       .tool-versions di asdf/mise (`nodejs 20.1 python 3.12`)
+//   kube        `current-context` di $KUBECONFIG o ~/.kube/config
+// I valori restano in cache per directory e segmento: si rileggono solo quando
+// cambia la data di modifica di un file (o directory) consultato o una variabile letta.
+
+lazy_static! {
+    static ref CACHE: Mutex<HashMap<(PathBuf, String), Entry>> = Mutex::new(HashMap::new());
+    static ref NODE_ENGINE: Regex = Regex::new(r#""engines"\s*:\s*\{[^}]*"node"\s*:\s*"([^"]*)""#).unwrap();
+}
+
+// Oltre questo numero di voci la cache si svuota
+const MAX_ENTRIES: usize = 256;
+
+// Da cosa dipende un valore: file e directory consultati con la loro data di
+// modifica (quella di una directory cambia quando vi si crea o cancella un file)
+// e variabili d'ambiente lette
+#[derive(Default)]
+struct Sources {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    vars: Vec<(&'static str, Option<OsString>)>,
+}
+
+impl Sources {
+    fn file(&mut self, path: &Path) {
+        self.files.push((path.to_path_buf(), modified(path)));
+    }
+
+    fn env(&mut self, name: &'static str) -> Option<String> {
+        let value = env::var_os(name);
+        self.vars.push((name, value.clone()));
+        value.and_then(|v| non_empty(v.to_string_lossy().into_owned()))
+    }
+
+    fn fresh(&self) -> bool {
+        self.files.iter().all(|(path, time)| modified(path) == *time)
+            && self.vars.iter().all(|(name, value)| env::var_os(name) == *value)
+    }
+}
+
+struct Entry {
+    value: Option<String>,
+    sources: Sources,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn non_empty(value: String) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+// Il file più vicino risalendo da `dir` (la directory stessa compresa)
+fn find_up(dir: &Path, names: &[&str], sources: &mut Sources) -> Option<PathBuf> {
+    for d in dir.ancestors() {
+        sources.file(d);
+        if let Some(found) = names.iter().map(|n| d.join(n)).find(|p| p.is_file()) {
+            sources.file(&found);
+            return Some(found);
+        }
+    }
+    None
+}
+
+fn virtualenv(sources: &mut Sources) -> Option<String> {
+    if let Some(venv) = sources.env("VIRTUAL_ENV") {
+        // `(progetto) ` come lo imposta activate, altrimenti il nome della directory
+        let prompt = sources.env("VIRTUAL_ENV_PROMPT").map(|p| p.trim_matches(|c: char| c == '(' || c == ')' || c.is_whitespace()).to_string());
+        return prompt.and_then(non_empty).or_else(|| {
+            Path::new(&venv).file_name().map(|n| n.to_string_lossy().into_owned())
+        });
+    }
+    sources.env("CONDA_DEFAULT_ENV")
+}
+
+// `[toolchain] channel = "1.75"`; il vecchio rust-toolchain può contenere solo il canale
+fn rust(dir: &Path, sources: &mut Sources) -> Option<String> {
+    if let Some(toolchain) = sources.env("RUSTUP_TOOLCHAIN") {
+        return Some(toolchain);
+    }
+    let text = fs::read_to_string(find_up(dir, &["rust-toolchain.toml", "rust-toolchain"], sources)?).ok()?;
+    match text.parse::<Table>() {
+        Ok(table) => table.get("toolchain")?.get("channel")?.as_str().map(str::to_string),
+        Err(_) => text.lines().next().map(str::to_string).and_then(non_empty),
+    }
+}
+
+fn node(dir: &Path, sources: &mut Sources) -> Option<String> {
+    for d in dir.ancestors() {
+        sources.file(d);
+        let nvmrc = d.join(".nvmrc");
+        if let Ok(version) = fs::read_to_string(&nvmrc) {
+            sources.file(&nvmrc);
+            return non_empty(version);
+        }
+        let package_json = d.join("package.json");
+        if let Ok(package) = fs::read_to_string(&package_json) {
+            sources.file(&package_json);
+            return NODE_ENGINE.captures(&package).and_then(|c| non_empty(c[1].to_string()));
+        }
+    }
+    None
+}
+
+// Righe `strumento versione [alternative]`, commenti con `#`
+fn tools(dir: &Path, sources: &mut Sources) -> Option<String> {
+    let text = fs::read_to_string(find_up(dir, &[".tool-versions"], sources)?).ok()?;
+    let tools: Vec<String> = text.lines()
+        .map(|l| l.split('#').next().unwrap_or_default())
+        .filter_map(|l| {
+            let mut words = l.split_whitespace();
+            Some(format!("{} {}", words.next()?, words.next()?))
+        })
+        .collect();
+    non_empty(tools.join(" "))
+}
+
+// Basta la chiave `current-context` in cima al file, senza leggere tutto lo YAML
+fn kube(sources: &mut Sources) -> Option<String> {
+    let path = sources.env("KUBECONFIG")
+        .and_then(|paths| env::split_paths(&paths).find(|p| p.is_file()))
+        .or_else(|| dirs::home_dir().map(|h| h.join(".kube/config")))?;
+    sources.file(&path);
+    let text = fs::read_to_string(path).ok()?;
+    text.lines()
+        .find_map(|l| l.strip_prefix("current-context:"))
+        .map(|c| c.trim().trim_matches(|q| q == '"' || q == '\'').to_string())
+        .and_then(non_empty)
+}
+
+/// Valore del segmento `name` per la directory corrente, `None` se non c'è nulla da mostrare
+pub fn segment(name: &str) -> Option<String> {
+    let dir = env::current_dir().ok()?;
+    let key = (dir, name.to_string());
+    let mut cache = CACHE.lock().unwrap();
+    if let Some(entry) = cache.get(&key).filter(|e| e.sources.fresh()) {
+        return entry.value.clone();
+    }
+    let mut sources = Sources::default();
+    let dir = &key.0;
+    let value = match name {
+        "virtualenv" => virtualenv(&mut sources),
+        "rust" => rust(dir, &mut sources),
+        "node" => node(dir, &mut sources),
+        "tools" => tools(dir, &mut sources),
+        "kube" => kube(&mut sources),
+        _ => None,
+    };
+    if cache.len() >= MAX_ENTRIES { cache.clear(); }
+    cache.insert(key, Entry { value: value.clone(), sources });
+    value
+}
@@ -0,0 +1,31 @@
+use reedline::{ValidationResult, Validator};
+use crate::shell::lexer;
+
+/// Validator per reedline: finché il comando non è completo (virgolette aperte,
+/// `\` / `|` / `&&` finali, blocchi `if ... then`/`foreach` non chiusi) Invio va a capo
+/// e il prompt mostra l'indicatore multilinea.
+pub struct GrshValidator;
+
+impl Validator for GrshValidator {
+    fn validate(&self, line: &str) -> ValidationResult {
+        if lexer::incomplete(line).is_some() || open_blocks(line) > 0 {
+            ValidationResult::Incomplete
+        } else {
+            ValidationResult::Complete
+        }
+    }
+}
+
+/// Numero di blocchi in stile tcsh ancora aperti nel testo (solo quelli che la
+/// shell sa eseguire: `if ... then`/`endif` e `foreach`/`end`)
+pub fn open_blocks(text: &str) -> usize {
+    let mut depth = 0usize;
+    for line in lexer::logical_lines(text) {
+        match lexer::block_step(&line) {
+            Some(true) => depth += 1,
+            Some(false) => depth = depth.saturating_sub(1),
+            None => {}
+        }
+    }
+    depth
+}